@@ -1,9 +1,12 @@
 use error::*;
+use json;
+use json::JsonValue;
 use nom::{alpha, digit, rest, space, IResult};
 use rusqlite;
 use rusqlite::types::{FromSql, FromSqlError,FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use serde_json;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt;
 use std::str;
 
@@ -169,6 +172,12 @@ impl PartialOrd for VariableCode {
     }
 }
 
+impl fmt::Display for VariableCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}_{}{}", self.table_code, self.column_id, self.var_type)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TableRecord {
     pub code: TableCode,
@@ -187,6 +196,22 @@ impl PartialOrd for TableRecord {
     }
 }
 
+/// A fulltext search hit. Matched terms are (start, end) byte ranges,
+/// highlighted when formatted for a terminal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchRecord {
+    pub table: TableRecord,
+    /// matches in `table.code.table_id`
+    pub table_id_matches: Vec<(usize, usize)>,
+    /// part of the label around matched terms
+    pub label_snippet: String,
+    pub label_matches: Vec<(usize, usize)>,
+}
+
+// ansi bold, for marking matched terms in search results
+const HIGHLIGHT_START: &str = "\x1b[1m";
+const HIGHLIGHT_END: &str = "\x1b[0m";
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct TableCode {
     pub prefix: TablePrefix,
@@ -220,6 +245,16 @@ impl PartialOrd for TableCode {
     }
 }
 
+impl fmt::Display for TableCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.prefix, self.table_id)?;
+        if let Some(ref suffix) = self.suffix {
+            write!(f, "{}", suffix)?;
+        }
+        Ok(())
+    }
+}
+
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum TablePrefix {
@@ -305,6 +340,16 @@ impl Estimate {
     }
 }
 
+impl Estimate {
+    /// Short name, same as stored in db
+    pub fn short_name(&self) -> &str {
+        match *self {
+            Estimate::OneYear => "1yr",
+            Estimate::FiveYear => "5yr",
+        }
+    }
+}
+
 impl fmt::Display for Estimate {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -344,25 +389,171 @@ pub fn format_table_name(record: &TableRecord) -> String {
     format!("{:9} | {}\n", code, record.label)
 }
 
-struct TableVersion {
-    records: Vec<VariableRecord>,
-    min_year: u32,
-    max_year: u32,
+pub struct TableVersion {
+    pub records: Vec<VariableRecord>,
+    pub min_year: u32,
+    pub max_year: u32,
+    /// (estimate, min year, max year) for each estimate this version
+    /// was seen in
+    pub estimates: Vec<(Estimate, u32, u32)>,
+}
+
+/// A column with its place in the label hierarchy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableColumn {
+    pub code: VariableCode,
+    pub label: String,
+    /// Label split into hierarchy levels, with "Estimate" and trailing
+    /// colons removed. Includes the "Total" root if the table has one.
+    pub path: Vec<String>,
+    pub depth: usize,
+    pub is_leaf: bool,
+}
+
+/// Splits a label into hierarchy levels.
+///
+/// Labels are formatted differently across years
+/// - pre-2016: "With a mortgage:!!Less than $800"
+/// - 2016+: "Estimate!!Total!!With a mortgage!!Less than $800"
+/// - 2019+: "Estimate!!Total:!!With a mortgage:!!Less than $800"
+///
+/// so strip the leading "Estimate", trailing colons, and a leading
+/// "Total" (which pre-2016 labels don't have) so that paths from all
+/// years can be compared.
+pub fn label_path(label: &str) -> Vec<String> {
+    let mut path: Vec<String> = label.split("!!")
+        .map(|level| level.trim().trim_right_matches(':').trim().to_owned())
+        .filter(|level| !level.is_empty())
+        .collect();
+
+    let is_prefix = |level: &str| {
+        let level = level.to_lowercase();
+        level == "estimate" || level == "margin of error"
+    };
+    if path.len() > 1 && is_prefix(path[0].as_str()) {
+        path.remove(0);
+    }
+
+    if path.len() > 1 && path[0].to_lowercase() == "total" {
+        path.remove(0);
+    }
+
+    path
+}
+
+/// Builds hierarchy for the records of one table version (one year and
+/// estimate).
+///
+/// A "Total" column is the root of all other columns. A column is a leaf
+/// if no other column is nested under it.
+pub fn table_columns(records: &[VariableRecord]) -> Vec<TableColumn> {
+    let paths: Vec<Vec<String>> = records.iter()
+        .map(|record| label_path(&record.label))
+        .collect();
+
+    let is_total = |path: &Vec<String>| {
+        path.len() == 1 && path[0].to_lowercase() == "total"
+    };
+    let has_total = paths.iter().any(|path| is_total(path));
+
+    let paths: Vec<Vec<String>> = paths.into_iter()
+        .map(|path| {
+            if has_total && !is_total(&path) {
+                let mut full = vec!["Total".to_owned()];
+                full.extend(path);
+                full
+            } else {
+                path
+            }
+        })
+        .collect();
+
+    records.iter().zip(paths.iter())
+        .map(|(record, path)| {
+            let is_leaf = !paths.iter().any(|other| {
+                other.len() > path.len() && other.starts_with(path)
+            });
+
+            TableColumn {
+                code: record.code.clone(),
+                label: record.label.clone(),
+                path: path.clone(),
+                depth: path.len().saturating_sub(1),
+                is_leaf: is_leaf,
+            }
+        })
+        .collect()
+}
+
+/// Records of columns whose label matches `keyword` (ignoring case), and
+/// of their ancestor columns so that the hierarchy is kept. Matched per
+/// year and estimate, since the hierarchy can change between them.
+pub fn grep_records(records: Vec<VariableRecord>, keyword: &str) -> Vec<VariableRecord> {
+    let keyword = keyword.to_lowercase();
+
+    let mut vintages: Vec<(Estimate, u32)> = records.iter()
+        .map(|record| (record.estimate.clone(), record.year))
+        .collect();
+    vintages.sort();
+    vintages.dedup();
+
+    // (estimate, year, column id) to keep, both estimate and margin of error
+    let mut keep: HashSet<(Estimate, u32, String)> = HashSet::new();
+
+    for (estimate, year) in vintages {
+        let mut vintage_records: Vec<VariableRecord> = records.iter()
+            .filter(|record| record.year == year && record.estimate == estimate)
+            .cloned()
+            .collect();
+        vintage_records.sort();
+
+        let columns: Vec<TableColumn> = table_columns(&vintage_records).into_iter()
+            .filter(|column| column.code.var_type == VariableType::Value)
+            .collect();
+
+        let matches: Vec<&TableColumn> = columns.iter()
+            .filter(|column| {
+                column.path.last()
+                    .map(|level| level.to_lowercase().contains(&keyword))
+                    .unwrap_or(false)
+            })
+            .collect();
+
+        for column in &columns {
+            let is_kept = matches.iter().any(|matched| matched.path.starts_with(&column.path));
+            if is_kept {
+                keep.insert((estimate.clone(), year, column.code.column_id.clone()));
+            }
+        }
+    }
+
+    records.into_iter()
+        .filter(|record| {
+            keep.contains(&(record.estimate.clone(), record.year, record.code.column_id.clone()))
+        })
+        .collect()
 }
 
 // TODO
 // This is a quick runtime hack to check for records.
 // I should go back and fix the table structure so that
 // calculation is done at refresh and runtime is faster
-fn get_table_versions(current_year: u32, all_versions: Vec<VariableRecord>) -> Vec<TableVersion> {
+//
+// Versions are tracked separately for each estimate, since a one-year
+// table can have a different shape from the five-year table in the same
+// year. A version is merged only with a version from the other estimate
+// that has the same shape and adjacent or overlapping years, so that a
+// table changing shape and then changing back stays as separate versions.
+pub fn get_table_versions(current_year: u32, all_versions: Vec<VariableRecord>) -> Vec<TableVersion> {
     let mut all_versions = all_versions;
     all_versions.sort();
 
     let mut versions: Vec<TableVersion> = Vec::new();
 
-    // check a year, and est combo
-    for year in 2009..current_year {
-        for estimate in &[Estimate::FiveYear, Estimate::OneYear] {
+    for estimate in &[Estimate::FiveYear, Estimate::OneYear] {
+        let mut est_versions: Vec<TableVersion> = Vec::new();
+
+        for year in 2009..current_year {
             let current_records: Vec<_> = all_versions.iter().filter(|record| {
                 record.year == year &&
                 record.estimate == *estimate
@@ -370,50 +561,109 @@ fn get_table_versions(current_year: u32, all_versions: Vec<VariableRecord>) -> V
             .cloned()
             .collect();
 
-            let mut is_new_version = false;
-
-            if let Some(last_version) = versions.last() {
-                // First check if tables are different length (with current being
-                // non-empty, so that it's useful to check)
-                // then must be different versions without checking further
-                if current_records.len() != 0 && current_records.len() != last_version.records.len() {
-                    is_new_version = true;
-                } else if current_records.len() != 0 {
-                    // if table lengths are same, then check further, if any labels
-                    // different, if current version is not empty vec
-                    let are_different_versions = last_version.records.iter()
-                        .zip(current_records.iter())
-                        .any(|(last, current)| {
-                            last.label.to_lowercase() != current.label.to_lowercase()
-                        });
-
-                    if are_different_versions {
-                        is_new_version = true;
-                    }
-                }
-            } else {
-                // If there is no last_version, it means versions
-                // is empty, so fill with the current one.
-                is_new_version = true;
+            if current_records.is_empty() {
+                continue;
             }
 
-            if is_new_version && !current_records.is_empty() {
-                let min_year = current_records[0].year;
+            let is_new_version = match est_versions.last() {
+                Some(last_version) => !is_same_shape(&last_version.records, &current_records),
+                // no versions yet for this estimate
+                None => true,
+            };
 
-                if let Some(last_version) = versions.last_mut() {
-                    last_version.max_year = min_year - 1;
-                }
-                versions.push(TableVersion {
+            if is_new_version {
+                est_versions.push(TableVersion {
                     records: current_records,
-                    min_year: min_year,
-                    max_year: current_year - 1,
+                    min_year: year,
+                    max_year: year,
+                    estimates: vec![(estimate.clone(), year, year)],
                 });
+            } else if let Some(last_version) = est_versions.last_mut() {
+                last_version.max_year = year;
+                last_version.estimates[0].2 = year;
+            }
+        }
+
+        // merge into a version from the other estimate with the same shape
+        for est_version in est_versions {
+            let same_shape = versions.iter_mut().find(|version| {
+                let other_estimate = version.estimates.iter().all(|&(ref e, _, _)| e != estimate);
+                let adjacent = est_version.min_year <= version.max_year + 1 &&
+                    version.min_year <= est_version.max_year + 1;
+
+                other_estimate && adjacent && is_same_shape(&version.records, &est_version.records)
+            });
+
+            match same_shape {
+                Some(version) => {
+                    version.min_year = version.min_year.min(est_version.min_year);
+                    version.max_year = version.max_year.max(est_version.max_year);
+                    version.estimates.extend(est_version.estimates);
+                },
+                None => versions.push(est_version),
             }
         }
     }
+
+    versions.sort_by(|a, b| {
+        (a.min_year, a.max_year).cmp(&(b.min_year, b.max_year))
+    });
     versions
 }
 
+/// Version of a table seen in the latest year. Versions are sorted by
+/// first year and mix estimates, so the last one isn't always the latest.
+/// When versions end in the same year, the five-year one is preferred.
+pub fn latest_version(versions: &[TableVersion]) -> Option<&TableVersion> {
+    versions.iter().max_by_key(|version| {
+        let is_five_year = version.estimates.iter()
+            .any(|&(ref estimate, _, _)| *estimate == Estimate::FiveYear);
+        (version.max_year, is_five_year)
+    })
+}
+
+/// Same columns with same labels (ignoring case). Records should be sorted.
+fn is_same_shape(a: &[VariableRecord], b: &[VariableRecord]) -> bool {
+    a.len() == b.len() &&
+    a.iter().zip(b.iter()).all(|(a, b)| {
+        a.code.column_id == b.code.column_id &&
+        a.code.var_type == b.code.var_type &&
+        a.label.to_lowercase() == b.label.to_lowercase()
+    })
+}
+
+/// Year range of each estimate of a version, see `TableVersion`
+pub fn version_estimates_json(estimates: &[(Estimate, u32, u32)]) -> JsonValue {
+    let estimates: Vec<JsonValue> = estimates.iter()
+        .map(|&(ref estimate, min_year, max_year)| {
+            object!{
+                "estimate" => estimate.short_name(),
+                "min_year" => min_year,
+                "max_year" => max_year
+            }
+        })
+        .collect();
+    estimates.into()
+}
+
+/// e.g. "5-year: 2009-2015, 1-year: 2012-2015"
+pub fn format_version_estimates(version: &TableVersion) -> String {
+    let estimates: Vec<String> = version.estimates.iter()
+        .map(|&(ref estimate, min_year, max_year)| {
+            let estimate = match *estimate {
+                Estimate::FiveYear => "5-year",
+                Estimate::OneYear => "1-year",
+            };
+            if min_year == max_year {
+                format!("{}: {}", estimate, min_year)
+            } else {
+                format!("{}: {}-{}", estimate, min_year, max_year)
+            }
+        })
+        .collect();
+    estimates.join(", ")
+}
+
 pub fn format_describe_table_raw(current_year: u32, records: Vec<VariableRecord>) -> String {
     let versions = get_table_versions(current_year, records);
 
@@ -421,6 +671,7 @@ pub fn format_describe_table_raw(current_year: u32, records: Vec<VariableRecord>
 
     for table_version in versions {
         res.push_str(&format!("::Years: {}-{}\n", table_version.min_year, table_version.max_year));
+        res.push_str(&format!("::Estimates: {}\n", format_version_estimates(&table_version)));
         for record in table_version.records {
             let mut code = vec![
                 record.code.table_code.prefix.to_string(),
@@ -444,7 +695,9 @@ pub fn format_describe_table_raw(current_year: u32, records: Vec<VariableRecord>
     res
 }
 
-pub fn format_describe_table_pretty(current_year: u32, records: Vec<VariableRecord>) -> String {
+/// With `moe`, each estimate column is shown with its margin of error
+/// column, and columns missing either one are flagged.
+pub fn format_describe_table_pretty(current_year: u32, records: Vec<VariableRecord>, moe: bool) -> String {
     let versions = get_table_versions(current_year, records);
 
     let indent = "    ";
@@ -453,20 +706,32 @@ pub fn format_describe_table_pretty(current_year: u32, records: Vec<VariableReco
 
     for table_version in versions {
         let min_year = table_version.min_year;
-        let max_year = table_version.max_year;
+        let estimates = format_version_estimates(&table_version);
 
-        if min_year == max_year {
+        if moe {
             res.push_str(&format!("\
-                code | label      (Year: {})\n\
-                -----+------------------------------------\n\
-            ", min_year)).to_owned();
+                code | moe  | label      ({})\n\
+                -----+------+-----------------------------\n\
+            ", estimates));
         } else {
             res.push_str(&format!("\
-                code | label      (Years: {}-{})\n\
+                code | label      ({})\n\
                 -----+------------------------------------\n\
-            ", min_year, max_year)).to_owned();
+            ", estimates));
         }
 
+        // a version has the same columns in every year and estimate
+        // it covers, so missing pairs are missing in all of them
+        let (value_ids, moe_ids) = {
+            let column_ids = |var_type: VariableType| -> Vec<String> {
+                table_version.records.iter()
+                    .filter(|record| record.code.var_type == var_type)
+                    .map(|record| record.code.column_id.clone())
+                    .collect()
+            };
+            (column_ids(VariableType::Value), column_ids(VariableType::MarginOfError))
+        };
+
         let table_records = table_version.records.into_iter().filter(|ref record| {
             record.code.var_type == VariableType::Value
         });
@@ -512,12 +777,43 @@ pub fn format_describe_table_pretty(current_year: u32, records: Vec<VariableReco
 
             let label = label.trim_right_matches(":");
 
+            if moe {
+                let moe_code = if moe_ids.contains(&col_id) {
+                    format!("{}{}", col_id, VariableType::MarginOfError)
+                } else {
+                    "-".to_owned()
+                };
 
-            res.push_str(&format!("{:5}| {}{}\n",
-                col_id,
-                indents,
-                label,
-            )[..]);
+                res.push_str(&format!("{:5}| {:5}| {}{}\n",
+                    format!("{}{}", col_id, VariableType::Value),
+                    moe_code,
+                    indents,
+                    label,
+                )[..]);
+            } else {
+                res.push_str(&format!("{:5}| {}{}\n",
+                    col_id,
+                    indents,
+                    label,
+                )[..]);
+            }
+        }
+
+        if moe {
+            for col_id in value_ids.iter().filter(|id| !moe_ids.contains(id)) {
+                res.push_str(&format!("warning: {}{} has no margin of error in {}\n",
+                    col_id,
+                    VariableType::Value,
+                    estimates,
+                ));
+            }
+            for col_id in moe_ids.iter().filter(|id| !value_ids.contains(id)) {
+                res.push_str(&format!("warning: {}{} has no estimate in {}\n",
+                    col_id,
+                    VariableType::MarginOfError,
+                    estimates,
+                ));
+            }
         }
         res.push_str("------------------------------------------\n\n")
     }
@@ -525,130 +821,714 @@ pub fn format_describe_table_pretty(current_year: u32, records: Vec<VariableReco
 
 }
 
-// TODO move all this processing into sql query
-// or at least refactor with format_describe
-pub fn format_etl_config(current_year: u32, records: Vec<VariableRecord>, etl_config_all: bool) -> String {
+/// Spreadsheet-friendly codebook, one row per variable per table version.
+/// `delimiter` should be ',' for csv or '\t' for tsv. `header` can be
+/// turned off when appending codebooks for several tables.
+pub fn format_codebook(
+    current_year: u32,
+    records: Vec<VariableRecord>,
+    delimiter: char,
+    header: bool,
+    ) -> String
+{
     let versions = get_table_versions(current_year, records);
 
-    let indents = "    ";
-
-    let mut res = String::new();
+    const HEADER: [&str; 9] = [
+        "code",
+        "column_id",
+        "var_type",
+        "label",
+        "indented_label",
+        "depth",
+        "min_year",
+        "max_year",
+        "estimates",
+    ];
+
+    let mut res = if header {
+        codebook_row(&HEADER, delimiter)
+    } else {
+        String::new()
+    };
+
+    for version in &versions {
+        let estimates: Vec<_> = version.estimates.iter()
+            .map(|&(ref estimate, _, _)| estimate.short_name())
+            .collect();
+        let estimates = estimates.join(";");
+        let min_year = version.min_year.to_string();
+        let max_year = version.max_year.to_string();
+
+        for column in table_columns(&version.records) {
+            let code = column.code.to_string();
+            let var_type = column.code.var_type.to_string();
+            let depth = column.depth.to_string();
+            let indented_label = format!("{}{}",
+                "    ".repeat(column.depth),
+                column.path.last().map(|s| s.as_str()).unwrap_or(""),
+            );
+
+            res.push_str(&codebook_row(&[
+                &code,
+                &column.code.column_id,
+                &var_type,
+                &column.label,
+                &indented_label,
+                &depth,
+                &min_year,
+                &max_year,
+                &estimates,
+            ], delimiter));
+        }
+    }
+    res
+}
 
-    for table_version in versions {
-        let mut records = table_version.records;
-        // Figure out better way to trim?
-        if records.len() == 2 {
-            records[1].label = records[1].label.trim_right_matches(":").to_owned();
+/// One delimited row, quoted for csv or cleaned for tsv
+pub fn codebook_row(fields: &[&str], delimiter: char) -> String {
+    let fields: Vec<String> = fields.iter().map(|field| {
+        if delimiter == '\t' {
+            // tsv has no quoting, so replace anything that would break a row
+            field.replace('\t', " ").replace('\n', " ")
+        } else if field.contains(delimiter) || field.contains('"') ||
+            field.contains('\n') || field.starts_with(' ')
+        {
+            // also quote leading whitespace, so that spreadsheets keep
+            // indentation
+            format!("\"{}\"", field.replace('"', "\"\""))
+        } else {
+            field.to_string()
         }
+    })
+    .collect();
 
-        let table_code = records[0].code.table_code.prefix.to_string() +
-            &records[0].code.table_code.table_id;
+    let mut row = fields.join(delimiter.to_string().as_str());
+    row.push('\n');
+    row
+}
 
-        let min_year = table_version.min_year;
-        let max_year = table_version.max_year;
+/// One variable across all vintages: its label in each year and
+/// estimate (flagging label changes), its margin of error companion,
+/// and its parent and child columns.
+///
+/// `records` are all records for the variable's table. Returns None
+/// if the variable isn't found.
+pub fn format_variable_history(code: &VariableCode, records: &[VariableRecord]) -> Option<String> {
+    let mut var_records: Vec<&VariableRecord> = records.iter()
+        .filter(|record| record.code == *code)
+        .collect();
+
+    if var_records.is_empty() {
+        return None;
+    }
 
-        let records = records.into_iter().filter(|record| {
-            let last = record.label.len();
+    var_records.sort_by(|a, b| {
+        // five-year first
+        (a.year, &b.estimate).cmp(&(b.year, &a.estimate))
+    });
 
-            if etl_config_all {
-                record.code.var_type == VariableType::Value
-            } else {
-                &record.label.as_bytes()[last-1..] != &b":"[..] &&
-                record.code.var_type == VariableType::Value
+    let mut res = format!("\nVariable {}\n", code);
+
+    // Labels, grouped into runs of years with the same label
+    res.push_str("\nLabels:\n============================================\n\n");
+    let mut changes = 0;
+    for estimate in &[Estimate::FiveYear, Estimate::OneYear] {
+        let est_records: Vec<_> = var_records.iter()
+            .filter(|record| record.estimate == *estimate)
+            .collect();
+
+        let mut runs: Vec<(u32, u32, &str)> = Vec::new();
+        for record in est_records {
+            // formatting-only differences across years (e.g. "Estimate!!")
+            // don't count as a change
+            let is_same = runs.last().map(|&(_, _, label)| {
+                label_path(label).iter().map(|l| l.to_lowercase()).collect::<Vec<_>>() ==
+                label_path(&record.label).iter().map(|l| l.to_lowercase()).collect::<Vec<_>>()
+            });
+            match is_same {
+                Some(true) => {
+                    runs.last_mut().unwrap().1 = record.year;
+                },
+                _ => runs.push((record.year, record.year, record.label.as_str())),
             }
-        });
+        }
 
+        for (i, &(min_year, max_year, label)) in runs.iter().enumerate() {
+            let years = if min_year == max_year {
+                format!("{}", min_year)
+            } else {
+                format!("{}-{}", min_year, max_year)
+            };
+            let changed = if i > 0 { changes += 1; "  (changed)" } else { "" };
+
+            res.push_str(&format!("{} {:9} | {}{}\n",
+                estimate.short_name(),
+                years,
+                label,
+                changed,
+            ));
+        }
+    }
+    if changes > 0 {
+        res.push_str(&format!("\nLabel changed {} time(s) between vintages\n", changes));
+    }
+
+    // Margin of error companion
+    let companion = VariableCode {
+        var_type: match code.var_type {
+            VariableType::Value => VariableType::MarginOfError,
+            VariableType::MarginOfError => VariableType::Value,
+        },
+        .. code.clone()
+    };
+    let missing_companion: Vec<String> = var_records.iter()
+        .filter(|record| {
+            !records.iter().any(|other| {
+                other.code == companion &&
+                other.year == record.year &&
+                other.estimate == record.estimate
+            })
+        })
+        .map(|record| format!("{} {}", record.year, record.estimate.short_name()))
+        .collect();
+
+    res.push_str(&format!("\n{}:\n============================================\n\n",
+        match companion.var_type {
+            VariableType::MarginOfError => "Margin of Error",
+            VariableType::Value => "Estimate",
+        }
+    ));
+    if missing_companion.len() == var_records.len() {
+        res.push_str(&format!("{} not found in any vintage\n", companion));
+    } else if missing_companion.is_empty() {
+        res.push_str(&format!("{} (all vintages)\n", companion));
+    } else {
+        res.push_str(&format!("{} (missing in: {})\n", companion, missing_companion.join(", ")));
+    }
+
+    // Parent and children, from latest vintage
+    let latest = var_records[var_records.len() - 1];
+    let vintage: Vec<VariableRecord> = records.iter()
+        .filter(|record| {
+            record.year == latest.year &&
+            record.estimate == latest.estimate &&
+            record.code.var_type == code.var_type
+        })
+        .cloned()
+        .collect();
+    let columns = table_columns(&vintage);
+
+    res.push_str(&format!("\nHierarchy ({} {}):\n============================================\n\n",
+        latest.year,
+        latest.estimate.short_name(),
+    ));
+    if let Some(column) = columns.iter().find(|column| column.code == *code) {
+        let parent = columns.iter().find(|other| {
+            other.path.len() + 1 == column.path.len() &&
+            column.path.starts_with(&other.path)
+        });
+        let children = columns.iter().filter(|other| {
+            other.path.len() == column.path.len() + 1 &&
+            other.path.starts_with(&column.path)
+        });
 
-        res.push_str(&format!("\
-            name: {:?}\n\
-            tag: \"acs\"\n\
-            min_year: {}\n\
-            max_year: {}\n\
-            acs_table:\n\
-                {}id: {:?}\n\
-                {}value_label: {:?}\n\
-                {}dimension_labels: [\n\
-                {}{:?},\n\
-                {}]\n\
-                {}columns:\n\
-        ",
-            "TABLENAME",
-            min_year,
-            max_year,
-            indents, table_code,
-            indents, "population", // logic to make this dynamic
-            indents,
-            indents.repeat(2), "DIMENSION",
-            indents,
-            indents,
+        match parent {
+            Some(parent) => res.push_str(&format!("parent:   {} | {}\n",
+                parent.code.column_id,
+                parent.path.join(" > "),
+            )),
+            None => res.push_str("parent:   none\n"),
+        }
+        res.push_str(&format!("column:   {} | {}\n",
+            column.code.column_id,
+            column.path.join(" > "),
         ));
+        for child in children {
+            res.push_str(&format!("child:    {} | {}\n",
+                child.code.column_id,
+                child.path.join(" > "),
+            ));
+        }
+    }
 
-        for record in records {
-            let pattern = if min_year < 2016 { ":!!" } else { "!!" };
+    Some(res)
+}
 
-            let label = if min_year >= 2016 {
-                let split_index = match record.label.find("!!") {
-                    Some(i) => i + 2,
-                    None => 0,
-                };
+/// Years and estimates one variant (prefix and suffix) of a table is
+/// available for.
+pub struct VariantAvailability {
+    pub code: TableCode,
+    pub est_years: HashMap<Estimate, Vec<u32>>,
+    /// (estimate, year) where a new version of the table starts
+    pub version_starts: Vec<(Estimate, u32)>,
+}
 
-                let (_, mut good_label) = record.label.split_at(split_index);
-                // ugh, have to remove Total from beginning of each
-                if record.code.column_id != "001" {
-                    good_label.to_owned().replace("Total!!", "")
-                } else {
-                    good_label.to_owned()
-                }
-            } else {
-                record.label
+/// (estimate, year) where each version after the first starts, per
+/// estimate.
+pub fn version_starts(versions: &[TableVersion]) -> Vec<(Estimate, u32)> {
+    let mut res = Vec::new();
+
+    for estimate in &[Estimate::FiveYear, Estimate::OneYear] {
+        let mut starts: Vec<u32> = versions.iter()
+            .filter_map(|version| {
+                version.estimates.iter()
+                    .find(|&&(ref e, _, _)| e == estimate)
+                    .map(|&(_, min_year, _)| min_year)
+            })
+            .collect();
+        starts.sort();
+
+        res.extend(starts.into_iter().skip(1).map(|year| (estimate.clone(), year)));
+    }
+    res
+}
+
+const AVAILABLE: &str = "●";
+const NEW_VERSION: &str = "◆";
+const NOT_AVAILABLE: &str = "·";
+
+/// Grid with years as columns, and a row for each variant and estimate.
+pub fn format_availability_grid(variants: &[VariantAvailability]) -> String {
+    let all_years: Vec<u32> = variants.iter()
+        .flat_map(|variant| variant.est_years.values())
+        .flat_map(|years| years.iter().cloned())
+        .collect();
+
+    let (min_year, max_year) = match (all_years.iter().min(), all_years.iter().max()) {
+        (Some(min), Some(max)) => (*min, *max),
+        _ => return "Not available for any year\n".to_owned(),
+    };
+
+    let mut res = format!("{:9} {:5}", "", "");
+    for year in min_year..max_year + 1 {
+        res.push_str(&format!("{:<5}", year));
+    }
+    res.push_str("\n");
+
+    for variant in variants {
+        for estimate in &[Estimate::FiveYear, Estimate::OneYear] {
+            let years = match variant.est_years.get(estimate) {
+                Some(years) => years,
+                None => continue,
             };
 
-            let label = label.replace(pattern, "_").replace("'", "");
-            let label = to_camelcase(&label);
+            res.push_str(&format!("{:9} {:5}", variant.code.to_string(), estimate.short_name()));
 
-            let indents = indents.repeat(2);
-            res.push_str(&format!("{}{}: {:?}\n",
-                indents,
-                record.code.column_id,
-                label,
-            )[..]);
+            for year in min_year..max_year + 1 {
+                let symbol = if !years.contains(&year) {
+                    NOT_AVAILABLE
+                } else if variant.version_starts.contains(&(estimate.clone(), year)) {
+                    NEW_VERSION
+                } else {
+                    AVAILABLE
+                };
+                res.push_str(&format!("{:5}", symbol));
+            }
+            res.push_str("\n");
         }
-        res.push_str("\n");
     }
+
+    res.push_str(&format!("\n{} available   {} new table version   {} not available\n",
+        AVAILABLE,
+        NEW_VERSION,
+        NOT_AVAILABLE,
+    ));
     res
 }
 
-pub fn format_est_years(est_years: &HashMap<Estimate, Vec<u32>>) -> String {
+/// `highlight` marks matched terms with ansi escapes, for terminals.
+pub fn format_fulltext_search_results(records: Vec<SearchRecord>, highlight: bool) -> String {
     let mut res = String::new();
-    for (estimate, years) in est_years.iter() {
-        res.push_str(&format!("{}: {:?}\n", estimate, years)[..]);
+    for record in &records {
+        let code = &record.table.code;
+
+        // pad by the width of the plain code, since the highlight
+        // escapes take up no room on screen.
+        let mut plain_len = code.prefix.to_string().len() + code.table_id.len();
+        let mut highlighted = code.prefix.to_string();
+        highlighted.push_str(&highlight_matches(&code.table_id, &record.table_id_matches, highlight));
+        if let Some(ref suffix) = code.suffix {
+            plain_len += suffix.len();
+            highlighted.push_str(suffix);
+        }
+        let padding = " ".repeat(9usize.saturating_sub(plain_len));
+
+        res.push_str(&format!("{}{} | {}\n",
+            highlighted,
+            padding,
+            highlight_matches(&record.label_snippet, &record.label_matches, highlight),
+        ));
+    }
+    res
+}
+
+/// Wraps each (start, end) byte range of `text` in highlight escapes.
+/// Ranges should be in order and not overlap.
+fn highlight_matches(text: &str, matches: &[(usize, usize)], highlight: bool) -> String {
+    if !highlight {
+        return text.to_owned();
+    }
+
+    let mut res = String::new();
+    let mut last = 0;
+    for &(start, end) in matches {
+        res.push_str(&text[last..start]);
+        res.push_str(HIGHLIGHT_START);
+        res.push_str(&text[start..end]);
+        res.push_str(HIGHLIGHT_END);
+        last = end;
+    }
+    res.push_str(&text[last..]);
+    res
+}
+
+/// All prefix and suffix variants of one table id, e.g. B25033 and
+/// C25033, or B19013 and its race iterations B19013A...B19013I
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableFamily {
+    pub table_id: String,
+    /// Variants that exist, sorted
+    pub variants: Vec<TableCode>,
+    pub label: String,
+    /// (estimate, min year, max year) over all variants
+    pub years: Vec<(Estimate, u32, u32)>,
+}
+
+/// Groups search results by table id, keeping the order in which each
+/// table id first appears. `variants` and `years` are left empty to be
+/// filled in from db, since not every variant matches the search.
+pub fn group_table_families(records: &[SearchRecord]) -> Vec<TableFamily> {
+    let mut families: Vec<TableFamily> = Vec::new();
+
+    for record in records {
+        let code = &record.table.code;
+
+        // shared label is from the base table if it's there, it won't
+        // mention a race iteration.
+        let is_base = code.prefix == TablePrefix::B && code.suffix.is_none();
+
+        if let Some(family) = families.iter_mut().find(|f| f.table_id == code.table_id) {
+            if is_base {
+                family.label = record.table.label.clone();
+            }
+            continue;
+        }
+
+        families.push(TableFamily {
+            table_id: code.table_id.clone(),
+            variants: Vec::new(),
+            label: record.table.label.clone(),
+            years: Vec::new(),
+        });
     }
+
+    families
+}
+
+/// Suffixes of each prefix, e.g. "B -,A,B; C -" for B19013, B19013A,
+/// B19013B and C19013. The base table (no suffix) is shown as "-".
+/// Variants should be sorted.
+pub fn format_family_variants(variants: &[TableCode]) -> String {
+    let mut groups: Vec<(TablePrefix, Vec<String>)> = Vec::new();
+
+    for code in variants {
+        let suffix = code.suffix.clone().unwrap_or_else(|| "-".to_owned());
+
+        let is_same_prefix = groups.last()
+            .map(|&(ref prefix, _)| *prefix == code.prefix)
+            .unwrap_or(false);
+
+        if is_same_prefix {
+            if let Some(&mut (_, ref mut suffixes)) = groups.last_mut() {
+                suffixes.push(suffix);
+            }
+        } else {
+            groups.push((code.prefix.clone(), vec![suffix]));
+        }
+    }
+
+    groups.iter()
+        .map(|&(ref prefix, ref suffixes)| format!("{} {}", prefix, suffixes.join(",")))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// (estimate, min year, max year), five-year estimate first.
+pub fn est_year_ranges(est_years: &HashMap<Estimate, Vec<u32>>) -> Vec<(Estimate, u32, u32)> {
+    let mut res: Vec<_> = est_years.iter()
+        .filter_map(|(estimate, years)| {
+            let min = years.iter().min();
+            let max = years.iter().max();
+            match (min, max) {
+                (Some(min), Some(max)) => Some((estimate.clone(), *min, *max)),
+                _ => None,
+            }
+        })
+        .collect();
+    res.sort_by(|a, b| b.0.cmp(&a.0));
     res
 }
 
-fn to_camelcase(s: &str) -> String {
-    s.split_whitespace().map(|word| {
-        let mut c = word.chars();
+/// Years available for each estimate, five-year estimate first, with
+/// consecutive years collapsed, e.g. "5-year: 2009-2015; 1-year: 2011-2013, 2015"
+pub fn format_available_years(est_years: &HashMap<Estimate, Vec<u32>>) -> String {
+    let mut estimates: Vec<_> = est_years.iter()
+        .filter(|&(_, years)| !years.is_empty())
+        .collect();
+    estimates.sort_by(|a, b| b.0.cmp(a.0));
+
+    let res: Vec<String> = estimates.into_iter()
+        .map(|(estimate, years)| {
+            let mut years = years.clone();
+            years.sort();
+            years.dedup();
+
+            // (start, end) of each run of consecutive years
+            let mut runs: Vec<(u32, u32)> = Vec::new();
+            for year in years {
+                if let Some(run) = runs.last_mut() {
+                    if run.1 + 1 == year {
+                        run.1 = year;
+                        continue;
+                    }
+                }
+                runs.push((year, year));
+            }
 
-        match c.next() {
-            Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
-            None => String::new(),
+            let runs: Vec<String> = runs.iter()
+                .map(|&(start, end)| {
+                    if start == end {
+                        start.to_string()
+                    } else {
+                        format!("{}-{}", start, end)
+                    }
+                })
+                .collect();
+
+            let estimate = match *estimate {
+                Estimate::FiveYear => "5-year",
+                Estimate::OneYear => "1-year",
+            };
+            format!("{}: {}", estimate, runs.join(", "))
+        })
+        .collect();
+
+    res.join("; ")
+}
+
+pub fn format_table_families(families: &[TableFamily]) -> String {
+    let mut res = format!("{:7}| {:25}| {:30}| {}\n",
+        "table", "variants", "years", "label");
+    res.push_str(&format!("{}\n", "-".repeat(80)));
+
+    for family in families {
+        let years: Vec<_> = family.years.iter()
+            .map(|&(ref estimate, min, max)| {
+                if min == max {
+                    format!("{} {}", estimate.short_name(), min)
+                } else {
+                    format!("{} {}-{}", estimate.short_name(), min, max)
+                }
+            })
+            .collect();
+
+        res.push_str(&format!("{:7}| {:25}| {:30}| {}\n",
+            family.table_id,
+            format_family_variants(&family.variants),
+            years.join(", "),
+            family.label,
+        ));
+    }
+    res
+}
+
+/// Structured description of a table: code, label, universe,
+/// availability, and each version with its columns. Serialized for
+/// `--format json`, and the context of `--template`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TableDescription {
+    pub code: String,
+    pub prefix: String,
+    pub table_id: String,
+    pub suffix: Option<String>,
+    pub label: String,
+    pub universe: Option<String>,
+    /// years of each estimate the table is in, by short name
+    pub availability: BTreeMap<String, Vec<u32>>,
+    pub versions: Vec<VersionDescription>,
+    /// columns of the latest version
+    pub columns: Vec<ColumnDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VersionDescription {
+    pub min_year: u32,
+    pub max_year: u32,
+    pub estimates: Vec<EstimateYears>,
+    pub columns: Vec<ColumnDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EstimateYears {
+    pub estimate: String,
+    pub min_year: u32,
+    pub max_year: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnDescription {
+    pub column_id: String,
+    pub var_type: String,
+    pub label: String,
+    pub label_path: Vec<String>,
+    pub depth: usize,
+    pub leaf: bool,
+}
+
+impl<'a> From<&'a TableColumn> for ColumnDescription {
+    fn from(column: &TableColumn) -> Self {
+        ColumnDescription {
+            column_id: column.code.column_id.clone(),
+            var_type: column.code.var_type.to_string(),
+            label: column.label.clone(),
+            label_path: column.path.clone(),
+            depth: column.depth,
+            leaf: column.is_leaf,
         }
-    }).collect()
+    }
 }
 
-pub fn format_fulltext_search_results(records: Vec<TableRecord>) -> String {
-    let mut res = String::new();
-    for record in &records {
-        res.push_str(&format_table_name(record));
+impl TableDescription {
+    pub fn to_json(&self) -> Result<JsonValue> {
+        let json_str = serde_json::to_string(self)?;
+        json::parse(&json_str)
+            .chain_err(|| "Error converting table description to json")
+    }
+}
+
+pub fn table_description(
+    current_year: u32,
+    table: &TableRecord,
+    universe: Option<String>,
+    est_years: &HashMap<Estimate, Vec<u32>>,
+    records: Vec<VariableRecord>,
+    ) -> TableDescription
+{
+    let versions = get_table_versions(current_year, records);
+
+    let columns = latest_version(&versions)
+        .map(|version| description_columns(&version.records))
+        .unwrap_or_default();
+
+    let versions = versions.iter()
+        .map(|version| {
+            VersionDescription {
+                min_year: version.min_year,
+                max_year: version.max_year,
+                estimates: version.estimates.iter()
+                    .map(|&(ref estimate, min_year, max_year)| {
+                        EstimateYears {
+                            estimate: estimate.short_name().to_owned(),
+                            min_year: min_year,
+                            max_year: max_year,
+                        }
+                    })
+                    .collect(),
+                columns: description_columns(&version.records),
+            }
+        })
+        .collect();
+
+    let availability = est_years.iter()
+        .map(|(estimate, years)| {
+            let mut years = years.clone();
+            years.sort();
+            (estimate.short_name().to_owned(), years)
+        })
+        .collect();
+
+    TableDescription {
+        code: table.code.to_string(),
+        prefix: table.code.prefix.to_string(),
+        table_id: table.code.table_id.clone(),
+        suffix: table.code.suffix.clone(),
+        label: table.label.clone(),
+        universe: universe,
+        availability: availability,
+        versions: versions,
+        columns: columns,
+    }
+}
+
+fn description_columns(records: &[VariableRecord]) -> Vec<ColumnDescription> {
+    table_columns(records).iter()
+        .map(ColumnDescription::from)
+        .collect()
+}
+
+pub fn table_record_json(record: &TableRecord) -> JsonValue {
+    object!{
+        "code" => record.code.to_string(),
+        "label" => record.label.clone()
+    }
+}
+
+pub fn variable_record_json(record: &VariableRecord) -> JsonValue {
+    object!{
+        "code" => record.code.to_string(),
+        "column_id" => record.code.column_id.clone(),
+        "var_type" => record.code.var_type.to_string(),
+        "label" => record.label.clone(),
+        "year" => record.year,
+        "estimate" => record.estimate.short_name()
+    }
+}
+
+pub fn est_years_json(est_years: &HashMap<Estimate, Vec<u32>>) -> JsonValue {
+    let mut res = JsonValue::new_object();
+    for (estimate, years) in est_years.iter() {
+        let mut years = years.clone();
+        years.sort();
+        res[estimate.short_name()] = years.into();
     }
     res
 }
 
+/// Records and columns for tests, from variable codes like "B25102_001E"
+#[cfg(test)]
+pub mod fixtures {
+    use super::*;
+
+    pub fn variable_code(code: &str) -> VariableCode {
+        parse_variable_code(code.as_bytes())
+            .to_result()
+            .expect("invalid variable code in test")
+    }
+
+    pub fn record(code: &str, label: &str, year: u32, estimate: Estimate) -> VariableRecord {
+        VariableRecord {
+            label: label.to_owned(),
+            code: variable_code(code),
+            year: year,
+            estimate: estimate,
+        }
+    }
+
+    pub fn column(code: &str, path: &[&str], is_leaf: bool) -> TableColumn {
+        TableColumn {
+            code: variable_code(code),
+            label: path.join("!!"),
+            path: path.iter().map(|s| s.to_string()).collect(),
+            depth: path.len() - 1,
+            is_leaf: is_leaf,
+        }
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::fixtures::{record, variable_code};
     use nom::IResult;
 
     #[test]
@@ -687,6 +1567,310 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_label_path() {
+        let expected = vec!["With a mortgage".to_owned(), "Less than $800".to_owned()];
+
+        assert_eq!(label_path("With a mortgage:!!Less than $800"), expected);
+        assert_eq!(label_path("Estimate!!Total!!With a mortgage!!Less than $800"), expected);
+        assert_eq!(label_path("Estimate!!Total:!!With a mortgage:!!Less than $800"), expected);
+        assert_eq!(label_path("Estimate!!Total"), vec!["Total".to_owned()]);
+        assert_eq!(label_path("Total:"), vec!["Total".to_owned()]);
+    }
+
+    #[test]
+    fn test_table_columns() {
+        let records = vec![
+            record("B25102_001E", "Total:", 2015, Estimate::FiveYear),
+            record("B25102_002E", "With a mortgage:", 2015, Estimate::FiveYear),
+            record("B25102_003E", "With a mortgage:!!Less than $800", 2015, Estimate::FiveYear),
+        ];
+
+        let columns = table_columns(&records);
+        let depths: Vec<_> = columns.iter().map(|c| c.depth).collect();
+        let leaves: Vec<_> = columns.iter().map(|c| c.is_leaf).collect();
+
+        assert_eq!(depths, vec![0, 1, 2]);
+        assert_eq!(leaves, vec![false, false, true]);
+        assert_eq!(columns[2].path, vec!["Total", "With a mortgage", "Less than $800"]);
+    }
+
+    #[test]
+    fn test_get_table_versions_per_estimate() {
+        let records = vec![
+            record("B25102_001E", "Total:", 2009, Estimate::FiveYear),
+            record("B25102_001E", "Total:", 2010, Estimate::FiveYear),
+            record("B25102_001E", "Total:", 2010, Estimate::OneYear),
+            record("B25102_002E", "With a mortgage:", 2010, Estimate::OneYear),
+            record("B25102_001E", "Total:", 2011, Estimate::OneYear),
+        ];
+
+        let versions = get_table_versions(2012, records);
+
+        // the one-year table in 2010 doesn't split the five-year version
+        assert_eq!(versions.len(), 2);
+        assert_eq!((versions[0].min_year, versions[0].max_year), (2009, 2011));
+        assert_eq!(versions[0].estimates, vec![
+            (Estimate::FiveYear, 2009, 2010),
+            (Estimate::OneYear, 2011, 2011),
+        ]);
+        assert_eq!((versions[1].min_year, versions[1].max_year), (2010, 2010));
+        assert_eq!(versions[1].estimates, vec![(Estimate::OneYear, 2010, 2010)]);
+    }
+
+    #[test]
+    fn test_latest_version() {
+        let records = vec![
+            record("B25102_001E", "Total:", 2009, Estimate::FiveYear),
+            record("B25102_001E", "Total:", 2012, Estimate::FiveYear),
+            record("B25102_001E", "Total:", 2010, Estimate::OneYear),
+            record("B25102_002E", "With a mortgage:", 2010, Estimate::OneYear),
+            record("B25102_001E", "Total:", 2011, Estimate::OneYear),
+            record("B25102_002E", "With a mortgage:", 2011, Estimate::OneYear),
+        ];
+
+        let versions = get_table_versions(2013, records);
+
+        // the one-year version starts later, but ends earlier
+        assert_eq!((versions[1].min_year, versions[1].max_year), (2010, 2011));
+        let latest = latest_version(&versions).unwrap();
+        assert_eq!((latest.min_year, latest.max_year), (2009, 2012));
+    }
+
+    #[test]
+    fn test_get_table_versions_shape_returns() {
+        let records = vec![
+            record("B25102_001E", "Total:", 2009, Estimate::FiveYear),
+            record("B25102_001E", "Total:", 2010, Estimate::FiveYear),
+            record("B25102_001E", "Total:", 2011, Estimate::FiveYear),
+            record("B25102_002E", "With a mortgage:", 2011, Estimate::FiveYear),
+            record("B25102_001E", "Total:", 2012, Estimate::FiveYear),
+            record("B25102_001E", "Total:", 2013, Estimate::FiveYear),
+        ];
+
+        let versions = get_table_versions(2014, records);
+
+        // shape A, then B, then A again are three versions
+        let ranges: Vec<_> = versions.iter()
+            .map(|version| (version.min_year, version.max_year))
+            .collect();
+        assert_eq!(ranges, vec![(2009, 2010), (2011, 2011), (2012, 2013)]);
+        assert_eq!(versions[2].estimates, vec![(Estimate::FiveYear, 2012, 2013)]);
+    }
+
+    #[test]
+    fn test_format_available_years() {
+        let mut est_years = HashMap::new();
+        est_years.insert(Estimate::OneYear, vec![2015, 2011, 2012, 2013]);
+        est_years.insert(Estimate::FiveYear, vec![2009, 2010, 2011]);
+
+        assert_eq!(
+            format_available_years(&est_years),
+            "5-year: 2009-2011; 1-year: 2011-2013, 2015"
+        );
+    }
+
+    #[test]
+    fn test_format_availability_grid() {
+        let code = |prefix: TablePrefix| TableCode {
+            prefix: prefix,
+            table_id: "25102".to_owned(),
+            suffix: None,
+        };
+
+        let mut b_years = HashMap::new();
+        b_years.insert(Estimate::FiveYear, vec![2009, 2010, 2011]);
+        b_years.insert(Estimate::OneYear, vec![2011]);
+        let mut c_years = HashMap::new();
+        c_years.insert(Estimate::OneYear, vec![2010, 2011]);
+
+        let variants = vec![
+            VariantAvailability {
+                code: code(TablePrefix::B),
+                est_years: b_years,
+                version_starts: vec![(Estimate::FiveYear, 2010)],
+            },
+            VariantAvailability {
+                code: code(TablePrefix::C),
+                est_years: c_years,
+                version_starts: vec![],
+            },
+        ];
+
+        assert_eq!(format_availability_grid(&variants), concat!(
+            "               2009 2010 2011 \n",
+            "B25102    5yr  ●    ◆    ●    \n",
+            "B25102    1yr  ·    ·    ●    \n",
+            "C25102    1yr  ·    ●    ●    \n",
+            "\n",
+            "● available   ◆ new table version   · not available\n",
+        ));
+    }
+
+    #[test]
+    fn test_table_description_json() {
+        let table = TableRecord {
+            code: TableCode {
+                prefix: TablePrefix::C,
+                table_id: "25102".to_owned(),
+                suffix: Some("A".to_owned()),
+            },
+            label: "Mortgage Status".to_owned(),
+        };
+        let records = vec![
+            record("C25102A_001E", "Total:", 2015, Estimate::FiveYear),
+            record("C25102A_002E", "Total:!!With a mortgage", 2015, Estimate::FiveYear),
+        ];
+        let mut est_years = HashMap::new();
+        est_years.insert(Estimate::FiveYear, vec![2015, 2014]);
+
+        let json = table_description(2016, &table, None, &est_years, records)
+            .to_json()
+            .unwrap();
+
+        assert_eq!(json["code"], "C25102A");
+        assert_eq!(json["suffix"], "A");
+        assert!(json["universe"].is_null());
+        assert_eq!(json["availability"]["5yr"], array![2014, 2015]);
+        assert_eq!(json["versions"][0]["estimates"][0]["estimate"], "5yr");
+        assert_eq!(json["columns"][1]["label_path"], array!["Total", "With a mortgage"]);
+        assert_eq!(json["columns"][1]["leaf"], true);
+        assert_eq!(json["columns"], json["versions"][0]["columns"]);
+    }
+
+    #[test]
+    fn test_grep_records() {
+        let records = vec![
+            record("B24010_001E", "Total:", 2015, Estimate::FiveYear),
+            record("B24010_002E", "Male:", 2015, Estimate::FiveYear),
+            record("B24010_003E", "Male:!!Computer, engineering, and science occupations:", 2015, Estimate::FiveYear),
+            record("B24010_004E", "Male:!!Computer, engineering, and science occupations:!!Architecture and engineering occupations", 2015, Estimate::FiveYear),
+            record("B24010_005E", "Male:!!Computer, engineering, and science occupations:!!Life sciences", 2015, Estimate::FiveYear),
+            record("B24010_006E", "Male:!!Service occupations", 2015, Estimate::FiveYear),
+            record("B24010_007E", "Female:", 2015, Estimate::FiveYear),
+        ];
+
+        let column_ids: Vec<String> = grep_records(records, "Architecture").into_iter()
+            .map(|record| record.code.column_id)
+            .collect();
+
+        assert_eq!(column_ids, vec!["001", "002", "003", "004"]);
+    }
+
+    #[test]
+    fn test_format_family_variants() {
+        let code = |prefix: TablePrefix, suffix: Option<&str>| TableCode {
+            prefix: prefix,
+            table_id: "19013".to_owned(),
+            suffix: suffix.map(|s| s.to_owned()),
+        };
+        let variants = vec![
+            code(TablePrefix::B, None),
+            code(TablePrefix::B, Some("A")),
+            code(TablePrefix::B, Some("B")),
+            code(TablePrefix::C, None),
+        ];
+
+        assert_eq!(format_family_variants(&variants), "B -,A,B; C -");
+    }
+
+    #[test]
+    fn test_format_describe_table_pretty_moe() {
+        let records = vec![
+            record("B25102_001E", "Total:", 2014, Estimate::FiveYear),
+            record("B25102_001M", "Total:", 2014, Estimate::FiveYear),
+            record("B25102_002E", "Total:!!With a mortgage", 2014, Estimate::FiveYear),
+            record("B25102_002M", "Total:!!With a mortgage", 2014, Estimate::FiveYear),
+            // margin of error missing
+            record("B25102_001E", "Total:", 2015, Estimate::FiveYear),
+            record("B25102_001M", "Total:", 2015, Estimate::FiveYear),
+            record("B25102_002E", "Total:!!With a mortgage", 2015, Estimate::FiveYear),
+        ];
+
+        assert_eq!(format_describe_table_pretty(2016, records, true), concat!(
+            "\nTable Columns:\n============================================\n\n",
+            "code | moe  | label      (5-year: 2014)\n",
+            "-----+------+-----------------------------\n",
+            "001E | 001M | Total\n",
+            "002E | 002M |     With a mortgage\n",
+            "------------------------------------------\n\n",
+            "code | moe  | label      (5-year: 2015)\n",
+            "-----+------+-----------------------------\n",
+            "001E | 001M | Total\n",
+            "002E | -    |     With a mortgage\n",
+            "warning: 002E has no margin of error in 5-year: 2015\n",
+            "------------------------------------------\n\n",
+        ));
+    }
+
+    #[test]
+    fn test_format_variable_history() {
+        let records = vec![
+            record("B25102_001E", "Total:", 2012, Estimate::FiveYear),
+            record("B25102_002E", "Total:!!With a mortgage:", 2012, Estimate::FiveYear),
+            record("B25102_002M", "Total:!!With a mortgage:", 2012, Estimate::FiveYear),
+            record("B25102_003E", "Total:!!With a mortgage:!!Less than $800", 2012, Estimate::FiveYear),
+            // relabelled, and margin of error missing
+            record("B25102_001E", "Total:", 2013, Estimate::FiveYear),
+            record("B25102_002E", "Total:!!Housing units with a mortgage:", 2013, Estimate::FiveYear),
+            record("B25102_003E", "Total:!!Housing units with a mortgage:!!Less than $800", 2013, Estimate::FiveYear),
+        ];
+
+        assert_eq!(format_variable_history(&variable_code("B25102_002E"), &records).unwrap(), concat!(
+            "\nVariable B25102_002E\n",
+            "\nLabels:\n============================================\n\n",
+            "5yr 2012      | Total:!!With a mortgage:\n",
+            "5yr 2013      | Total:!!Housing units with a mortgage:  (changed)\n",
+            "\nLabel changed 1 time(s) between vintages\n",
+            "\nMargin of Error:\n============================================\n\n",
+            "B25102_002M (missing in: 2013 5yr)\n",
+            "\nHierarchy (2013 5yr):\n============================================\n\n",
+            "parent:   001 | Total\n",
+            "column:   002 | Total > Housing units with a mortgage\n",
+            "child:    003 | Total > Housing units with a mortgage > Less than $800\n",
+        ));
+
+        assert_eq!(format_variable_history(&variable_code("B25102_004E"), &records), None);
+    }
+
+    #[test]
+    fn test_format_fulltext_search_results() {
+        let records = vec![SearchRecord {
+            table: TableRecord {
+                code: TableCode {
+                    prefix: TablePrefix::B,
+                    table_id: "19013".to_owned(),
+                    suffix: Some("A".to_owned()),
+                },
+                label: "Median Household Income".to_owned(),
+            },
+            table_id_matches: vec![],
+            label_snippet: "Median Household Income".to_owned(),
+            label_matches: vec![(17, 23)],
+        }];
+
+        assert_eq!(
+            format_fulltext_search_results(records.clone(), false),
+            "B19013A   | Median Household Income\n"
+        );
+        assert_eq!(
+            format_fulltext_search_results(records, true),
+            "B19013A   | Median Household \x1b[1mIncome\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_codebook_row() {
+        assert_eq!(
+            codebook_row(&["B25102_001E", "Total:", "    With a mortgage, 1"], ','),
+            "B25102_001E,Total:,\"    With a mortgage, 1\"\n"
+        );
+        assert_eq!(
+            codebook_row(&["B25102_001E", "say \"hi\"\tthere"], '\t'),
+            "B25102_001E\tsay \"hi\" there\n"
+        );
+    }
+
     #[test]
     fn test_parse_table_code_only() {
         let input = "B24126".as_bytes();
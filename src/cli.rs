@@ -1,12 +1,20 @@
 use clap::{Arg, App, AppSettings, SubCommand};
 
 use acs::{
+    Estimate,
     TablePrefix,
+    VariableCode,
+    VariableRecord,
     parse_table_id,
     parse_suffix,
+    parse_variable_code,
 };
 use error::*;
 
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+
 pub fn cli_command() -> Result<ExplorerCommand> {
     let app_m = App::new("ACS Explorer")
         .version(crate_version!())
@@ -22,14 +30,38 @@ pub fn cli_command() -> Result<ExplorerCommand> {
             .alias("s")
             .arg(Arg::with_name("search_tables")
                 .takes_value(true)
-                .help("enter text to search for")))
+                .help("enter text to search for"))
+            .arg(Arg::with_name("limit")
+                .short("l")
+                .long("limit")
+                .takes_value(true)
+                .help("maximum number of results to show"))
+            .arg(Arg::with_name("offset")
+                .short("o")
+                .long("offset")
+                .takes_value(true)
+                .help("number of results to skip"))
+            .arg(Arg::with_name("interactive")
+                .short("i")
+                .long("interactive")
+                .conflicts_with("group")
+                .help("filter results as you type, and select a table to describe"))
+            .arg(Arg::with_name("group")
+                .short("g")
+                .long("group")
+                .help("group B, C and iteration variants of a table into one row")))
         .subcommand(SubCommand::with_name("describe")
             .display_order(20)
             .about("Get information about a specific table")
             .alias("d")
             .arg(Arg::with_name("describe_table")
                 .takes_value(true)
-                .help("enter table id to describe"))
+                .multiple(true)
+                .help("enter table ids to describe, or - to read ids from stdin. Without a B or C prefix, both are described"))
+            .arg(Arg::with_name("from_file")
+                .long("from-file")
+                .takes_value(true)
+                .help("read table ids from a file, one or more per line, # for comments"))
             .arg(Arg::with_name("etl_config")
                 .short("e")
                 .long("etl")
@@ -38,19 +70,106 @@ pub fn cli_command() -> Result<ExplorerCommand> {
             .arg(Arg::with_name("etl_config_all")
                 .short("a")
                 .long("all")
-                .requires("etl_config")
                 .help("format results to etl config with all vars"))
             .arg(Arg::with_name("raw")
                 .short("r")
                 .long("raw")
-                .help("format results as raw data from api")))
+                .help("format results as raw data from api"))
+            .arg(Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["pretty", "tree", "raw", "etl", "json", "csv", "tsv"])
+                .conflicts_with_all(&["etl_config", "raw"])
+                .help("output format, default pretty"))
+            .arg(Arg::with_name("diff")
+                .long("diff")
+                .conflicts_with_all(&["etl_config", "raw", "format"])
+                .help("show column changes between table versions"))
+            .arg(Arg::with_name("family")
+                .long("family")
+                .conflicts_with_all(&["etl_config", "raw", "format", "diff", "timeline", "crosswalk"])
+                .help("list race and ethnicity iterations (suffixes) of a table, and compare them"))
+            .arg(Arg::with_name("rollup")
+                .long("rollup")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .conflicts_with_all(&["etl_config", "raw", "format", "diff", "family", "timeline", "crosswalk"])
+                .help("which B columns are summed into each C column, for every year and estimate"))
+            .arg(Arg::with_name("timeline")
+                .long("timeline")
+                .conflicts_with_all(&["etl_config", "raw", "format", "diff"])
+                .help("show which years and estimates each column is available for"))
+            .arg(Arg::with_name("crosswalk")
+                .long("crosswalk")
+                .takes_value(true)
+                .possible_values(&["csv", "json"])
+                .conflicts_with_all(&["etl_config", "raw", "format", "diff", "timeline", "rollup"])
+                .help("map column ids between every pair of table versions"))
+            .arg(Arg::with_name("template")
+                .long("template")
+                .takes_value(true)
+                .conflicts_with_all(&["etl_config", "raw", "format", "diff", "family", "timeline", "rollup", "crosswalk"])
+                .help("render each table through a handlebars template file, see README for the fields available"))
+            .arg(Arg::with_name("year")
+                .short("y")
+                .long("year")
+                .takes_value(true)
+                .help("only show the table as it was in this year"))
+            .arg(Arg::with_name("estimate")
+                .long("estimate")
+                .takes_value(true)
+                .possible_values(&["1", "5"])
+                .help("only show the table as it was in this estimate, 1 or 5 year"))
+            .arg(Arg::with_name("grep")
+                .long("grep")
+                .takes_value(true)
+                .help("only show columns with a label containing this text, and their parents"))
+            .arg(Arg::with_name("max_depth")
+                .long("max-depth")
+                .takes_value(true)
+                .help("with tree format, collapse columns nested deeper than this"))
+            .arg(Arg::with_name("moe")
+                .long("moe")
+                .help("show margin of error column for each estimate column")))
+        .subcommand(SubCommand::with_name("var")
+            .display_order(25)
+            .about("Get information about a variable across all years")
+            .alias("v")
+            .arg(Arg::with_name("variable")
+                .required(true)
+                .help("enter variable code, e.g. B25102_003E (E is default)")))
         .subcommand(SubCommand::with_name("refresh")
             .display_order(30)
             .about("refresh all years and estimates of acs data summaries"))
+        .subcommand(SubCommand::with_name("shell")
+            .display_order(35)
+            .about("interactive shell for search and describe"))
+        .subcommand(SubCommand::with_name("synonyms")
+            .display_order(40)
+            .about("list and edit synonyms used to expand searches")
+            .subcommand(SubCommand::with_name("add")
+                .about("add a synonym for a search term")
+                .arg(Arg::with_name("term")
+                    .required(true)
+                    .help("search term"))
+                .arg(Arg::with_name("synonym")
+                    .required(true)
+                    .help("synonym for search term, may be quoted phrase")))
+            .subcommand(SubCommand::with_name("remove")
+                .about("remove a synonym for a search term")
+                .arg(Arg::with_name("term")
+                    .required(true)
+                    .help("search term"))
+                .arg(Arg::with_name("synonym")
+                    .required(true)
+                    .help("synonym to remove"))))
         .after_help("fulltext search (`search` table subcommand):\n\
-            \t- Currently implemented to use exact match.\n\
+            \t- Matches whole words, or prefixes with `*`, e.g. `inc*`.\n\
             \t- Case insensitive.\n\
-            \t- Searches table name, and table id (no prefix or suffix). ")
+            \t- Searches table name, and table id (no prefix or suffix).\n\
+            \t- Results ranked by relevance, table id matches ranked first.\n\
+            \t- Words are stemmed, and expanded using `synonyms`. ")
         .get_matches();
 
     // for global flags. Check at each level/subcommand if the flag is present,
@@ -62,47 +181,208 @@ pub fn cli_command() -> Result<ExplorerCommand> {
         ("search", Some(sub_m)) => {
             if sub_m.is_present("verbose") { verbose = true; }
 
-            let search = sub_m
-                .value_of("search_tables")
-                .ok_or("No text entered")?;
+            let interactive = sub_m.is_present("interactive");
+            let group = sub_m.is_present("group");
+
+            // search text is optional when interactive, since it can
+            // be typed in later
+            let search = match sub_m.value_of("search_tables") {
+                Some(search) => search,
+                None if interactive => "",
+                None => return Err("No text entered".into()),
+            };
+
+            let limit = match sub_m.value_of("limit") {
+                Some(limit) => Some(limit.parse::<u32>()
+                    .map_err(|_| format!("{:?} is not a valid limit", limit))?),
+                None => None,
+            };
+
+            let offset = match sub_m.value_of("offset") {
+                Some(offset) => offset.parse::<u32>()
+                    .map_err(|_| format!("{:?} is not a valid offset", offset))?,
+                None => 0,
+            };
 
             Ok(ExplorerCommand {
-                command: Command::FulltextSearch(search.to_owned()),
+                command: Command::FulltextSearch {
+                    search: search.to_owned(),
+                    limit: limit,
+                    offset: offset,
+                    interactive: interactive,
+                    group: group,
+                },
                 verbose: verbose,
             })
         },
         ("describe", Some(sub_m)) => {
             if sub_m.is_present("verbose") { verbose = true; }
 
-            let etl_config = sub_m.is_present("etl_config");
             let etl_config_all = sub_m.is_present("etl_config_all");
-            let raw = sub_m.is_present("raw");
 
-            let query = sub_m
-                .value_of("describe_table")
-                .ok_or("Table id required for query")?;
+            // --etl and --raw are shorthand for --format
+            let format = if sub_m.is_present("etl_config") {
+                DescribeFormat::Etl
+            } else if sub_m.is_present("raw") {
+                DescribeFormat::Raw
+            } else if sub_m.is_present("diff") {
+                DescribeFormat::Diff
+            } else if sub_m.is_present("family") {
+                DescribeFormat::Family
+            } else if let Some(rollup) = sub_m.value_of("rollup") {
+                if rollup == "json" {
+                    DescribeFormat::RollupJson
+                } else {
+                    DescribeFormat::RollupText
+                }
+            } else if sub_m.is_present("timeline") {
+                DescribeFormat::Timeline
+            } else if let Some(crosswalk) = sub_m.value_of("crosswalk") {
+                if crosswalk == "json" {
+                    DescribeFormat::CrosswalkJson
+                } else {
+                    DescribeFormat::CrosswalkCsv
+                }
+            } else if let Some(path) = sub_m.value_of("template") {
+                let mut source = String::new();
+                File::open(path)
+                    .and_then(|mut file| file.read_to_string(&mut source))
+                    .chain_err(|| format!("Error reading template {:?}", path))?;
+                DescribeFormat::Template { source: source }
+            } else {
+                match sub_m.value_of("format") {
+                    Some("tree") => {
+                        let max_depth = match sub_m.value_of("max_depth") {
+                            Some(max_depth) => Some(max_depth.parse::<usize>()
+                                .map_err(|_| format!("{:?} is not a valid max depth", max_depth))?),
+                            None => None,
+                        };
+                        DescribeFormat::Tree { max_depth: max_depth }
+                    },
+                    Some("raw") => DescribeFormat::Raw,
+                    Some("etl") => DescribeFormat::Etl,
+                    Some("json") => DescribeFormat::Json,
+                    Some("csv") => DescribeFormat::Csv,
+                    Some("tsv") => DescribeFormat::Tsv,
+                    _ => DescribeFormat::Pretty,
+                }
+            };
 
-            let query = parse_table_query(query.as_bytes())
-                .to_result()
-                .map_err(|_| format!(
-                    "{:?} is not a valid Table ID format, see --help",
-                    query)
-                )?;
+            if etl_config_all && format != DescribeFormat::Etl {
+                return Err("--all requires etl format".into());
+            }
+
+            match format {
+                DescribeFormat::Tree { .. } => (),
+                _ if sub_m.is_present("max_depth") => {
+                    return Err("--max-depth requires tree format".into());
+                },
+                _ => (),
+            }
+
+            let moe = sub_m.is_present("moe");
+
+            if moe && format != DescribeFormat::Pretty {
+                return Err("--moe requires pretty format".into());
+            }
+
+            let year = match sub_m.value_of("year") {
+                Some(year) => Some(year.parse::<u32>()
+                    .map_err(|_| format!("{:?} is not a valid year", year))?),
+                None => None,
+            };
+
+            let estimate = match sub_m.value_of("estimate") {
+                Some("1") => Some(Estimate::OneYear),
+                Some("5") => Some(Estimate::FiveYear),
+                _ => None,
+            };
+
+            let mut table_ids = Vec::new();
+            let mut batch = sub_m.is_present("from_file");
+
+            if let Some(values) = sub_m.values_of("describe_table") {
+                batch = batch || values.len() > 1;
+                for value in values {
+                    if value == "-" {
+                        batch = true;
+                        let stdin = io::stdin();
+                        table_ids.extend(read_manifest(stdin.lock())?);
+                    } else {
+                        table_ids.push(value.to_owned());
+                    }
+                }
+            }
+
+            if let Some(path) = sub_m.value_of("from_file") {
+                if path == "-" {
+                    let stdin = io::stdin();
+                    table_ids.extend(read_manifest(stdin.lock())?);
+                } else {
+                    let file = File::open(path)
+                        .chain_err(|| format!("Error opening manifest {:?}", path))?;
+                    table_ids.extend(read_manifest(BufReader::new(file))?);
+                }
+            }
 
-            if query.prefix.is_none() {
-                return Err("Prefix required for table code".into());
+            if table_ids.is_empty() {
+                return Err("Table id required for query".into());
+            }
+
+            let mut queries = Vec::new();
+            for table_id in &table_ids {
+                let query = parse_table_query(table_id.as_bytes())
+                    .to_result()
+                    .map_err(|_| format!(
+                        "{:?} is not a valid Table ID format, see --help",
+                        table_id)
+                    )?;
+
+                queries.push(query);
             }
 
             Ok(ExplorerCommand {
                 command: Command::DescribeTable {
-                    query: query,
-                    etl_config: etl_config,
-                    etl_config_all: etl_config_all,
-                    raw: raw,
+                    queries: queries,
+                    options: DescribeOptions {
+                        format: format,
+                        etl_config_all: etl_config_all,
+                        vintage: Vintage {
+                            year: year,
+                            estimate: estimate,
+                        },
+                        moe: moe,
+                        grep: sub_m.value_of("grep").map(|grep| grep.to_owned()),
+                        batch: batch,
+                    },
                 },
                 verbose: verbose,
             })
         },
+        ("var", Some(sub_m)) => {
+            if sub_m.is_present("verbose") { verbose = true; }
+
+            // required arg, so unwrap is safe
+            let variable = sub_m.value_of("variable").unwrap();
+
+            // default to estimate if var type left off
+            let mut input = variable.to_uppercase();
+            if input.ends_with(|c: char| c.is_digit(10)) {
+                input.push('E');
+            }
+
+            let code = parse_variable_code(input.as_bytes())
+                .to_result()
+                .map_err(|_| format!(
+                    "{:?} is not a valid Variable code format, see --help",
+                    variable)
+                )?;
+
+            Ok(ExplorerCommand {
+                command: Command::DescribeVariable(code),
+                verbose: verbose,
+            })
+        },
         ("refresh", Some(sub_m)) => {
             if sub_m.is_present("verbose") { verbose = true; }
 
@@ -111,10 +391,57 @@ pub fn cli_command() -> Result<ExplorerCommand> {
                 verbose: verbose,
             })
         },
+        ("shell", Some(sub_m)) => {
+            if sub_m.is_present("verbose") { verbose = true; }
+
+            Ok(ExplorerCommand {
+                command: Command::Shell,
+                verbose: verbose,
+            })
+        },
+        ("synonyms", Some(sub_m)) => {
+            let synonyms_command = match sub_m.subcommand() {
+                ("add", Some(add_m)) => SynonymsCommand::Add {
+                    // required args, so unwrap is safe
+                    term: add_m.value_of("term").unwrap().to_owned(),
+                    synonym: add_m.value_of("synonym").unwrap().to_owned(),
+                },
+                ("remove", Some(remove_m)) => SynonymsCommand::Remove {
+                    term: remove_m.value_of("term").unwrap().to_owned(),
+                    synonym: remove_m.value_of("synonym").unwrap().to_owned(),
+                },
+                _ => SynonymsCommand::List,
+            };
+
+            Ok(ExplorerCommand {
+                command: Command::Synonyms(synonyms_command),
+                verbose: verbose,
+            })
+        },
         _ => Err("Not a valid subcommand".into()),
     }
 }
 
+/// Table ids from a manifest, separated by whitespace or commas.
+/// Anything after a # is a comment.
+fn read_manifest<R: BufRead>(reader: R) -> Result<Vec<String>> {
+    let mut res = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = match line.find('#') {
+            Some(i) => &line[..i],
+            None => &line[..],
+        };
+
+        res.extend(line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|table_id| !table_id.is_empty())
+            .map(|table_id| table_id.to_owned())
+        );
+    }
+    Ok(res)
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct ExplorerCommand {
     pub command: Command,
@@ -124,12 +451,108 @@ pub struct ExplorerCommand {
 #[derive(Debug, Clone, PartialEq)]
 pub enum Command {
     Refresh,
-    FulltextSearch(String),
+    DescribeVariable(VariableCode),
+    FulltextSearch {
+        search: String,
+        limit: Option<u32>,
+        offset: u32,
+        interactive: bool,
+        group: bool,
+    },
     DescribeTable {
-        query: TableIdQuery,
-        etl_config: bool,
-        etl_config_all: bool,
-        raw: bool,
+        queries: Vec<TableIdQuery>,
+        options: DescribeOptions,
+    },
+    Shell,
+    Synonyms(SynonymsCommand),
+}
+
+/// How tables are described, see `describe::describe_tables`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DescribeOptions {
+    pub format: DescribeFormat,
+    /// subtotal columns in etl configs too, not just leaves
+    pub etl_config_all: bool,
+    /// only records in this vintage are described
+    pub vintage: Vintage,
+    /// margin of error columns in pretty format
+    pub moe: bool,
+    /// only columns matching this keyword, and their parents
+    pub grep: Option<String>,
+    /// several ids, a manifest or stdin were given, so json output is
+    /// always an array
+    pub batch: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DescribeFormat {
+    Pretty,
+    Tree {
+        max_depth: Option<usize>,
+    },
+    Raw,
+    Etl,
+    Json,
+    Csv,
+    Tsv,
+    Diff,
+    Timeline,
+    Family,
+    RollupText,
+    RollupJson,
+    CrosswalkCsv,
+    CrosswalkJson,
+    /// Handlebars template source
+    Template {
+        source: String,
+    },
+}
+
+/// Year and estimate to filter a table description to. None means
+/// all years, or all estimates.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Vintage {
+    pub year: Option<u32>,
+    pub estimate: Option<Estimate>,
+}
+
+impl Vintage {
+    pub fn contains(&self, record: &VariableRecord) -> bool {
+        self.includes(&record.estimate, record.year)
+    }
+
+    pub fn includes(&self, estimate: &Estimate, year: u32) -> bool {
+        self.year.map(|y| year == y).unwrap_or(true) &&
+        self.estimate.as_ref().map(|e| estimate == e).unwrap_or(true)
+    }
+}
+
+/// e.g. "2010 1-year"
+impl fmt::Display for Vintage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(year) = self.year {
+            parts.push(year.to_string());
+        }
+        match self.estimate {
+            Some(Estimate::OneYear) => parts.push("1-year".to_owned()),
+            Some(Estimate::FiveYear) => parts.push("5-year".to_owned()),
+            None => (),
+        }
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SynonymsCommand {
+    List,
+    Add {
+        term: String,
+        synonym: String,
+    },
+    Remove {
+        term: String,
+        synonym: String,
     },
 }
 
@@ -140,7 +563,7 @@ pub struct TableIdQuery {
     pub suffix: Option<String>,
 }
 
-named!(parse_table_query<&[u8], TableIdQuery>,
+named!(pub parse_table_query<&[u8], TableIdQuery>,
     do_parse!(
         prefix: parse_prefix_query >>
         table_id: parse_table_id >>
@@ -0,0 +1,379 @@
+// Maps column ids between versions of a table, for building time
+// series across versions.
+//
+// Columns are matched in passes, each pass only looking at columns
+// not matched yet:
+// 1. same label path: exact if column id is the same, high otherwise
+// 2. same label path ignoring punctuation and spacing: medium
+// 3. same last label level, under parents that were matched: medium
+// 4. same column id and depth: low
+
+use acs::{
+    Estimate,
+    TableColumn,
+    TableVersion,
+    VariableCode,
+    VariableRecord,
+    VariableType,
+    codebook_row,
+    get_table_versions,
+    table_columns,
+    version_estimates_json,
+};
+use diff::{match_by_path, path_key, version_boundaries};
+
+use json::JsonValue;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+    Exact,
+}
+
+impl fmt::Display for Confidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Confidence::Low => write!(f, "low"),
+            Confidence::Medium => write!(f, "medium"),
+            Confidence::High => write!(f, "high"),
+            Confidence::Exact => write!(f, "exact"),
+        }
+    }
+}
+
+/// Crosswalk between one pair of table versions
+pub struct VersionCrosswalk {
+    pub old_years: (u32, u32),
+    pub new_years: (u32, u32),
+    /// see `TableVersion`
+    pub old_estimates: Vec<(Estimate, u32, u32)>,
+    pub new_estimates: Vec<(Estimate, u32, u32)>,
+    pub old_columns: Vec<TableColumn>,
+    pub new_columns: Vec<TableColumn>,
+    /// (old code, new code, confidence), see `crosswalk`
+    pub mappings: Vec<(VariableCode, VariableCode, Confidence)>,
+}
+
+impl VersionCrosswalk {
+    /// Label paths of the old and new column of a mapping
+    fn labels(&self, old_code: &VariableCode, new_code: &VariableCode) -> (String, String) {
+        let label = |columns: &[TableColumn], code: &VariableCode| {
+            columns.iter()
+                .find(|column| column.code == *code)
+                .map(|column| column.path.join(" > "))
+                .unwrap_or_default()
+        };
+        (label(&self.old_columns, old_code), label(&self.new_columns, new_code))
+    }
+}
+
+/// Maps old columns to new columns. Columns should be of one var type.
+pub fn crosswalk(old: &[TableColumn], new: &[TableColumn]) -> Vec<(VariableCode, VariableCode, Confidence)> {
+    crosswalk_indices(old, new).into_iter()
+        .map(|(i, j, confidence)| (old[i].code.clone(), new[j].code.clone(), confidence))
+        .collect()
+}
+
+/// Crosswalks between versions of a table where one follows another
+/// within the same estimate (see `version_boundaries`), for both
+/// estimate and margin of error columns. A one-year version isn't
+/// crosswalked to a five-year version.
+pub fn crosswalk_versions(versions: &[TableVersion]) -> Vec<VersionCrosswalk> {
+    let mut res = Vec::new();
+
+    let columns: Vec<Vec<TableColumn>> = versions.iter()
+        .map(|version| table_columns(&version.records))
+        .collect();
+
+    for (i, j) in version_boundaries(versions) {
+        let mut mappings = Vec::new();
+
+        // only match columns of the same var type
+        for var_type in &[VariableType::Value, VariableType::MarginOfError] {
+            let of_type = |columns: &[TableColumn]| -> Vec<TableColumn> {
+                columns.iter()
+                    .filter(|column| column.code.var_type == *var_type)
+                    .cloned()
+                    .collect()
+            };
+            mappings.extend(crosswalk(&of_type(&columns[i]), &of_type(&columns[j])));
+        }
+        mappings.sort();
+
+        res.push(VersionCrosswalk {
+            old_years: (versions[i].min_year, versions[i].max_year),
+            new_years: (versions[j].min_year, versions[j].max_year),
+            old_estimates: versions[i].estimates.clone(),
+            new_estimates: versions[j].estimates.clone(),
+            old_columns: columns[i].clone(),
+            new_columns: columns[j].clone(),
+            mappings: mappings,
+        });
+    }
+    res
+}
+
+fn crosswalk_indices(old: &[TableColumn], new: &[TableColumn]) -> Vec<(usize, usize, Confidence)> {
+    let mut old_to_new: Vec<Option<usize>> = vec![None; old.len()];
+    let mut new_matched = vec![false; new.len()];
+    let mut res = Vec::new();
+
+    // 1. same label path
+    for (i, j) in match_by_path(old, new) {
+        let confidence = if old[i].code.column_id == new[j].code.column_id {
+            Confidence::Exact
+        } else {
+            Confidence::High
+        };
+        old_to_new[i] = Some(j);
+        new_matched[j] = true;
+        res.push((i, j, confidence));
+    }
+
+    // 2. same label path, ignoring punctuation and spacing
+    for i in 0..old.len() {
+        if old_to_new[i].is_some() {
+            continue;
+        }
+        let key = loose_key(&old[i].path);
+        let found = (0..new.len())
+            .find(|&j| !new_matched[j] && loose_key(&new[j].path) == key);
+
+        if let Some(j) = found {
+            old_to_new[i] = Some(j);
+            new_matched[j] = true;
+            res.push((i, j, Confidence::Medium));
+        }
+    }
+
+    // 3. same last level, under matched parents. Parents come before
+    // children, so one pass in column order picks up nested matches.
+    for i in 0..old.len() {
+        if old_to_new[i].is_some() {
+            continue;
+        }
+        let new_parent = match parent_index(old, i).and_then(|p| old_to_new[p]) {
+            Some(new_parent) => new_parent,
+            None => continue,
+        };
+        let leaf = old[i].path.last().map(|level| loose_level(level));
+
+        let found = (0..new.len()).find(|&j| {
+            !new_matched[j] &&
+            parent_index(new, j) == Some(new_parent) &&
+            new[j].path.last().map(|level| loose_level(level)) == leaf
+        });
+
+        if let Some(j) = found {
+            old_to_new[i] = Some(j);
+            new_matched[j] = true;
+            res.push((i, j, Confidence::Medium));
+        }
+    }
+
+    // 4. same column id and depth
+    for i in 0..old.len() {
+        if old_to_new[i].is_some() {
+            continue;
+        }
+        let found = (0..new.len()).find(|&j| {
+            !new_matched[j] &&
+            new[j].code.column_id == old[i].code.column_id &&
+            new[j].depth == old[i].depth
+        });
+
+        if let Some(j) = found {
+            old_to_new[i] = Some(j);
+            new_matched[j] = true;
+            res.push((i, j, Confidence::Low));
+        }
+    }
+
+    res.sort();
+    res
+}
+
+fn parent_index(columns: &[TableColumn], i: usize) -> Option<usize> {
+    let path = &columns[i].path;
+    if path.len() < 2 {
+        return None;
+    }
+    let parent_key = path_key(&TableColumn {
+        path: path[..path.len() - 1].to_vec(),
+        .. columns[i].clone()
+    });
+    columns.iter().position(|column| path_key(column) == parent_key)
+}
+
+fn loose_level(level: &str) -> String {
+    level.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+fn loose_key(path: &[String]) -> Vec<String> {
+    path.iter().map(|level| loose_level(level)).collect()
+}
+
+/// e.g. "5yr 2012-2015;1yr 2013-2015"
+fn format_estimate_ranges(estimates: &[(Estimate, u32, u32)]) -> String {
+    let ranges: Vec<String> = estimates.iter()
+        .map(|&(ref estimate, min_year, max_year)| {
+            format!("{} {}-{}", estimate.short_name(), min_year, max_year)
+        })
+        .collect();
+    ranges.join(";")
+}
+
+pub fn format_crosswalk_csv(current_year: u32, records: Vec<VariableRecord>, header: bool) -> String {
+    const HEADER: [&str; 11] = [
+        "old_min_year",
+        "old_max_year",
+        "old_estimates",
+        "new_min_year",
+        "new_max_year",
+        "new_estimates",
+        "old_code",
+        "new_code",
+        "confidence",
+        "old_label",
+        "new_label",
+    ];
+
+    let versions = get_table_versions(current_year, records);
+
+    let mut res = if header {
+        codebook_row(&HEADER, ',')
+    } else {
+        String::new()
+    };
+
+    for version_crosswalk in crosswalk_versions(&versions) {
+        let old_estimates = format_estimate_ranges(&version_crosswalk.old_estimates);
+        let new_estimates = format_estimate_ranges(&version_crosswalk.new_estimates);
+
+        for &(ref old_code, ref new_code, ref confidence) in &version_crosswalk.mappings {
+            let (old_label, new_label) = version_crosswalk.labels(old_code, new_code);
+
+            res.push_str(&codebook_row(&[
+                &version_crosswalk.old_years.0.to_string(),
+                &version_crosswalk.old_years.1.to_string(),
+                &old_estimates,
+                &version_crosswalk.new_years.0.to_string(),
+                &version_crosswalk.new_years.1.to_string(),
+                &new_estimates,
+                &old_code.to_string(),
+                &new_code.to_string(),
+                &confidence.to_string(),
+                &old_label,
+                &new_label,
+            ], ','));
+        }
+    }
+    res
+}
+
+pub fn format_crosswalk_json(current_year: u32, records: Vec<VariableRecord>) -> JsonValue {
+    let versions = get_table_versions(current_year, records);
+
+    let crosswalks: Vec<JsonValue> = crosswalk_versions(&versions).iter()
+        .map(|version_crosswalk| {
+            let mappings: Vec<JsonValue> = version_crosswalk.mappings.iter()
+                .map(|&(ref old_code, ref new_code, ref confidence)| {
+                    let (old_label, new_label) = version_crosswalk.labels(old_code, new_code);
+                    object!{
+                        "old_code" => old_code.to_string(),
+                        "new_code" => new_code.to_string(),
+                        "confidence" => confidence.to_string(),
+                        "old_label" => old_label,
+                        "new_label" => new_label
+                    }
+                })
+                .collect();
+
+            object!{
+                "old_min_year" => version_crosswalk.old_years.0,
+                "old_max_year" => version_crosswalk.old_years.1,
+                "old_estimates" => version_estimates_json(&version_crosswalk.old_estimates),
+                "new_min_year" => version_crosswalk.new_years.0,
+                "new_max_year" => version_crosswalk.new_years.1,
+                "new_estimates" => version_estimates_json(&version_crosswalk.new_estimates),
+                "mappings" => mappings
+            }
+        })
+        .collect();
+
+    crosswalks.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acs::fixtures::{column, record};
+
+    #[test]
+    fn test_crosswalk() {
+        let old = vec![
+            column("B08301_001E", &["Total"], false),
+            column("B08301_002E", &["Total", "Car, truck, or van"], false),
+            column("B08301_003E", &["Total", "Car, truck, or van", "Drove alone"], true),
+            column("B08301_004E", &["Total", "Public transportation"], true),
+            column("B08301_005E", &["Total", "Bicycle"], true),
+        ];
+        let new = vec![
+            column("B08301_001E", &["Total"], false),
+            column("B08301_002E", &["Total", "Car, truck or van"], false),
+            column("B08301_003E", &["Total", "Car, truck or van", "Drove alone"], true),
+            column("B08301_004E", &["Total", "Public transportation (excluding taxicab)"], true),
+            column("B08301_005E", &["Total", "Taxicab"], true),
+            column("B08301_006E", &["Total", "Bicycle"], true),
+        ];
+
+        let codes = |i: usize, j: usize, confidence: Confidence| {
+            (old[i].code.clone(), new[j].code.clone(), confidence)
+        };
+
+        assert_eq!(crosswalk(&old, &new), vec![
+            codes(0, 0, Confidence::Exact),
+            codes(1, 1, Confidence::Medium),
+            codes(2, 2, Confidence::Medium),
+            codes(3, 3, Confidence::Low),
+            codes(4, 5, Confidence::High),
+        ]);
+    }
+
+    #[test]
+    fn test_crosswalk_versions_by_estimate() {
+        let records = vec![
+            record("B08301_001E", "Total:", 2012, Estimate::FiveYear),
+            record("B08301_002E", "Total:!!Car, truck, or van", 2012, Estimate::FiveYear),
+            record("B08301_001E", "Total:", 2013, Estimate::FiveYear),
+            record("B08301_002E", "Total:!!Car, truck, or van", 2013, Estimate::FiveYear),
+            // one-year only shape
+            record("B08301_001E", "Total:", 2012, Estimate::OneYear),
+            record("B08301_002E", "Total:!!Bicycle", 2012, Estimate::OneYear),
+            record("B08301_001E", "Total:", 2014, Estimate::FiveYear),
+            record("B08301_002E", "Total:!!Car, truck, or van", 2014, Estimate::FiveYear),
+            record("B08301_003E", "Total:!!Bicycle", 2014, Estimate::FiveYear),
+        ];
+        let versions = get_table_versions(2015, records);
+        assert_eq!(versions.len(), 3);
+
+        let crosswalks = crosswalk_versions(&versions);
+        assert_eq!(crosswalks.len(), 1);
+        assert_eq!(crosswalks[0].old_estimates, vec![(Estimate::FiveYear, 2012, 2013)]);
+        assert_eq!(crosswalks[0].new_estimates, vec![(Estimate::FiveYear, 2014, 2014)]);
+
+        let codes: Vec<(String, String)> = crosswalks[0].mappings.iter()
+            .map(|&(ref old_code, ref new_code, _)| (old_code.to_string(), new_code.to_string()))
+            .collect();
+        assert_eq!(codes, vec![
+            ("B08301_001E".to_owned(), "B08301_001E".to_owned()),
+            ("B08301_002E".to_owned(), "B08301_002E".to_owned()),
+        ]);
+    }
+}
@@ -0,0 +1,709 @@
+// Putting together a table description from multiple explorer
+// queries. Shared between the describe subcommand, the
+// interactive picker, and the shell.
+
+use acs::{
+    TablePrefix,
+    TableRecord,
+    TableCode,
+    TableDescription,
+    VariableRecord,
+    VariableType,
+    VariantAvailability,
+    format_availability_grid,
+    format_available_years,
+    format_codebook,
+    format_table_name,
+    format_describe_table_pretty,
+    format_describe_table_raw,
+    get_table_versions,
+    grep_records,
+    latest_version,
+    table_columns,
+    table_description,
+    version_starts,
+};
+use cli::{DescribeFormat, DescribeOptions, TableIdQuery, Vintage};
+use crosswalk::{format_crosswalk_csv, format_crosswalk_json};
+use diff::format_describe_table_diff;
+use error::*;
+use etl::{etl_configs, format_etl_configs};
+use explorer::Explorer;
+use family::{FamilyMember, format_table_family};
+use rollup::{
+    format_rollup,
+    format_rollup_vintages,
+    rollup_vintages,
+    rollup_vintages_json,
+    shared_vintages,
+    vintage_rollup,
+};
+use suggest::{suggest, Suggestions};
+use template::Template;
+use timeline::format_column_timeline;
+use tree::{format_describe_table_tree, terminal_width};
+
+use json::JsonValue;
+use std::fmt;
+
+/// A table that couldn't be described
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unavailable {
+    NotFound(TableCode, Suggestions),
+    /// Table id given without prefix, and neither B nor C table found
+    NotFoundAnyPrefix {
+        table_id: String,
+        suffix: Option<String>,
+        suggestions: Suggestions,
+    },
+    /// Table exists, but no column labels contain keyword
+    NoMatchingColumns {
+        code: TableCode,
+        keyword: String,
+    },
+    /// Table exists, but not in the requested vintage
+    NotInVintage {
+        code: TableCode,
+        vintage: Vintage,
+        /// see `format_available_years`
+        available: String,
+    },
+}
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Unavailable::NotFound(ref code, ref suggestions) => {
+                write!(f, "Table {} not found.", code)?;
+                if !suggestions.is_empty() {
+                    write!(f, "\n{}", suggestions)?;
+                }
+                Ok(())
+            },
+            Unavailable::NotFoundAnyPrefix { ref table_id, ref suffix, ref suggestions } => {
+                write!(f, "Table {}{} not found with B or C prefix.",
+                    table_id,
+                    suffix.as_ref().map(|s| s.as_str()).unwrap_or(""),
+                )?;
+                if !suggestions.is_empty() {
+                    write!(f, "\n{}", suggestions)?;
+                }
+                Ok(())
+            },
+            Unavailable::NoMatchingColumns { ref code, ref keyword } => {
+                write!(f, "No columns of table {} match {:?}.", code, keyword)
+            },
+            Unavailable::NotInVintage { ref code, ref vintage, ref available } => {
+                write!(f, "Table {} not available in {}, available years are {}",
+                    code,
+                    vintage,
+                    available,
+                )
+            },
+        }
+    }
+}
+
+impl Unavailable {
+    /// The table doesn't exist at all, as opposed to having nothing to
+    /// show for the vintage or columns asked for
+    pub fn is_not_found(&self) -> bool {
+        match *self {
+            Unavailable::NotFound(..) | Unavailable::NotFoundAnyPrefix { .. } => true,
+            Unavailable::NotInVintage { .. } | Unavailable::NoMatchingColumns { .. } => false,
+        }
+    }
+}
+
+/// Describes each table in `queries`, combining output for all tables
+/// into one in the format given in `options`.
+///
+/// Returns output, and the tables that couldn't be described.
+pub fn describe_tables(
+    explorer: &mut Explorer,
+    current_year: u32,
+    queries: &[TableIdQuery],
+    options: &DescribeOptions,
+    ) -> Result<(String, Vec<Unavailable>)>
+{
+    let format = &options.format;
+    let vintage = &options.vintage;
+    let grep = &options.grep;
+    let batch = options.batch;
+
+    let mut outputs = Vec::new();
+    let mut json_outputs = Vec::new();
+    let mut missing = Vec::new();
+
+    // without a prefix, both B and C tables are described
+    let mut codes = Vec::new();
+    for query in queries {
+        let code = |prefix: &TablePrefix| TableCode {
+            prefix: prefix.clone(),
+            table_id: query.table_id.clone(),
+            suffix: query.suffix.clone(),
+        };
+
+        match query.prefix {
+            Some(ref prefix) => codes.push(code(prefix)),
+            None => {
+                let mut found = false;
+                for prefix in &[TablePrefix::B, TablePrefix::C] {
+                    let code = code(prefix);
+                    let exists = if *format == DescribeFormat::Family {
+                        has_family(explorer, &code)?
+                    } else {
+                        has_table(explorer, &code)?
+                    };
+                    if exists {
+                        codes.push(code);
+                        found = true;
+                    }
+                }
+                if !found {
+                    missing.push(Unavailable::NotFoundAnyPrefix {
+                        table_id: query.table_id.clone(),
+                        suffix: query.suffix.clone(),
+                        suggestions: Suggestions::default(),
+                    });
+                }
+            },
+        }
+    }
+
+    // decided from the command line rather than how many tables were
+    // found, so the output shape is the same every time. An id without
+    // a prefix can describe both a B and C table.
+    let is_batch = batch || queries.iter().any(|query| query.prefix.is_none());
+
+    // parsed once, so syntax errors are reported before any queries
+    let template = match *format {
+        DescribeFormat::Template { ref source } => Some(Template::new(source)?),
+        _ => None,
+    };
+
+    // roll ups are for both B and C tables, so only done once per id
+    let mut rollups_done: Vec<(String, Option<String>)> = Vec::new();
+
+    for code in codes {
+        // a family can have iterations without a base table, and isn't
+        // filtered by vintage or columns
+        if *format == DescribeFormat::Family {
+            if has_family(explorer, &code)? {
+                outputs.push(describe_family(
+                    explorer,
+                    current_year,
+                    &code.prefix,
+                    &code.table_id,
+                )?);
+            } else {
+                missing.push(Unavailable::NotFound(code, Suggestions::default()));
+            }
+            continue;
+        }
+
+        let table_records = explorer.describe_table(
+            &code.prefix,
+            &code.table_id,
+            &code.suffix,
+        )?;
+
+        if table_records.is_empty() {
+            missing.push(Unavailable::NotFound(code, Suggestions::default()));
+            continue;
+        }
+
+        // all records are kept for table information
+        let records: Vec<VariableRecord> = table_records.iter()
+            .filter(|record| vintage.contains(record))
+            .cloned()
+            .collect();
+
+        if records.is_empty() {
+            let est_years = explorer.query_est_years(
+                &code.prefix,
+                &code.table_id,
+                &code.suffix,
+            )?;
+            missing.push(Unavailable::NotInVintage {
+                code: code,
+                vintage: vintage.clone(),
+                available: format_available_years(&est_years),
+            });
+            continue;
+        }
+
+        let records = match *grep {
+            Some(ref keyword) => grep_records(records, keyword),
+            None => records,
+        };
+
+        if records.is_empty() {
+            missing.push(Unavailable::NoMatchingColumns {
+                code: code,
+                // grep is Some, since all tables have columns
+                keyword: grep.clone().unwrap_or_default(),
+            });
+            continue;
+        }
+
+        match *format {
+            DescribeFormat::Etl => {
+                let table = table_record(explorer, &code.prefix, &code.table_id, &code.suffix)?;
+                let universe = explorer.query_universe(&code.prefix, &code.table_id, &code.suffix)?;
+                let configs = etl_configs(current_year, records, options.etl_config_all, &table.label, universe);
+                outputs.push(format_etl_configs(&configs)?);
+            },
+            DescribeFormat::Template { .. } => {
+                let template = template.as_ref()
+                    .ok_or("Template format without a parsed template")?;
+                let description = describe_description(
+                    explorer,
+                    current_year,
+                    &code.prefix,
+                    &code.table_id,
+                    &code.suffix,
+                    records,
+                )?;
+                outputs.push(template.render(&description)?);
+            },
+            DescribeFormat::Raw => {
+                // raw output has no table code otherwise
+                let mut out = if is_batch {
+                    format!("::Table: {}\n", code)
+                } else {
+                    String::new()
+                };
+                out.push_str(&format_describe_table_raw(current_year, records));
+                outputs.push(out);
+            },
+            DescribeFormat::Json => {
+                json_outputs.push(describe_json(
+                    explorer,
+                    current_year,
+                    &code.prefix,
+                    &code.table_id,
+                    &code.suffix,
+                    records,
+                )?);
+            },
+            DescribeFormat::Csv | DescribeFormat::Tsv => {
+                let delimiter = if *format == DescribeFormat::Csv { ',' } else { '\t' };
+                let header = outputs.is_empty();
+                outputs.push(format_codebook(current_year, records, delimiter, header));
+            },
+            DescribeFormat::Diff => {
+                outputs.push(format_describe_table_diff(current_year, records));
+            },
+            // described before the table is looked up
+            DescribeFormat::Family => (),
+            DescribeFormat::RollupText | DescribeFormat::RollupJson => {
+                let key = (code.table_id.clone(), code.suffix.clone());
+                if rollups_done.contains(&key) {
+                    continue;
+                }
+                rollups_done.push(key);
+
+                let (b_records, c_records) = rollup_records(
+                    explorer,
+                    &code.table_id,
+                    &code.suffix,
+                    vintage,
+                )?;
+                let stored = explorer.query_rollups(&code.table_id, &code.suffix)?;
+                let vintage_rollups = rollup_vintages(&b_records, &c_records, &stored, grep);
+
+                if *format == DescribeFormat::RollupJson {
+                    json_outputs.push(object!{
+                        "b_table" => format!("B{}{}", code.table_id, code.suffix.clone().unwrap_or_default()),
+                        "c_table" => format!("C{}{}", code.table_id, code.suffix.clone().unwrap_or_default()),
+                        "vintages" => rollup_vintages_json(&vintage_rollups)
+                    });
+                } else if vintage_rollups.is_empty() {
+                    outputs.push(format!("No B and C tables {} in the same year and estimate\n",
+                        code.table_id,
+                    ));
+                } else {
+                    outputs.push(format_rollup_vintages(&vintage_rollups));
+                }
+            },
+            DescribeFormat::Timeline => {
+                // years where no column matched grep are still shown
+                let mut est_years = explorer.query_est_years(&code.prefix, &code.table_id, &code.suffix)?;
+                for (estimate, years) in &mut est_years {
+                    years.retain(|year| vintage.includes(estimate, *year));
+                }
+                outputs.push(format_column_timeline(records, &est_years));
+            },
+            DescribeFormat::CrosswalkCsv => {
+                let header = outputs.is_empty();
+                outputs.push(format_crosswalk_csv(current_year, records, header));
+            },
+            DescribeFormat::CrosswalkJson => {
+                json_outputs.push(object!{
+                    "code" => code.to_string(),
+                    "crosswalks" => format_crosswalk_json(current_year, records)
+                });
+            },
+            DescribeFormat::Tree { max_depth } => {
+                let mut out = format_describe_table_tree(
+                    current_year,
+                    records,
+                    max_depth,
+                    terminal_width(),
+                );
+                out.push_str(&table_information(
+                    explorer,
+                    current_year,
+                    &code.prefix,
+                    &code.table_id,
+                    &code.suffix,
+                    &table_records,
+                )?);
+                outputs.push(out);
+            },
+            DescribeFormat::Pretty => {
+                let mut out = format_describe_table_pretty(current_year, records, options.moe);
+                out.push_str(&table_information(
+                    explorer,
+                    current_year,
+                    &code.prefix,
+                    &code.table_id,
+                    &code.suffix,
+                    &table_records,
+                )?);
+                outputs.push(out);
+            },
+        }
+    }
+
+    // suggestions need all table codes, so only fetched if something
+    // wasn't found
+    if missing.iter().any(Unavailable::is_not_found) {
+        let all_codes = explorer.table_codes()?;
+
+        for unavailable in &mut missing {
+            match *unavailable {
+                Unavailable::NotFound(ref code, ref mut suggestions) => {
+                    *suggestions = suggest(
+                        &Some(code.prefix.clone()),
+                        &code.table_id,
+                        &code.suffix,
+                        &all_codes,
+                    );
+                },
+                Unavailable::NotFoundAnyPrefix { ref table_id, ref suffix, ref mut suggestions } => {
+                    *suggestions = suggest(&None, table_id, suffix, &all_codes);
+                },
+                Unavailable::NotInVintage { .. } | Unavailable::NoMatchingColumns { .. } => (),
+            }
+        }
+    }
+
+    // how C columns map onto B columns, for tables described by id only
+    match *format {
+        DescribeFormat::Pretty | DescribeFormat::Tree { .. } => {
+            for query in queries.iter().filter(|query| query.prefix.is_none()) {
+                if let Some(summary) = describe_rollup_summary(
+                    explorer,
+                    &query.table_id,
+                    &query.suffix,
+                    vintage,
+                    grep,
+                )? {
+                    outputs.push(summary);
+                }
+            }
+        },
+        _ => (),
+    }
+
+    let out = match *format {
+        DescribeFormat::Json | DescribeFormat::CrosswalkJson | DescribeFormat::RollupJson => {
+            // one roll up covers both the B and C table
+            let is_batch = if *format == DescribeFormat::RollupJson {
+                batch
+            } else {
+                is_batch
+            };
+
+            if is_batch {
+                JsonValue::from(json_outputs).pretty(2)
+            } else {
+                json_outputs.pop().map(|json| json.pretty(2)).unwrap_or_default()
+            }
+        },
+        // separate yaml documents
+        DescribeFormat::Etl => outputs.join("---\n"),
+        // the template decides how tables are separated
+        DescribeFormat::Csv | DescribeFormat::Tsv | DescribeFormat::CrosswalkCsv |
+        DescribeFormat::Template { .. } => {
+            outputs.concat()
+        },
+        DescribeFormat::Raw | DescribeFormat::Pretty | DescribeFormat::Tree { .. } |
+        DescribeFormat::Diff | DescribeFormat::Timeline | DescribeFormat::Family |
+        DescribeFormat::RollupText => {
+            outputs.join("\n")
+        },
+    };
+
+    Ok((out, missing))
+}
+
+/// Pretty output of table columns, followed by table information.
+///
+/// Returns None if table is not found.
+pub fn describe_pretty(
+    explorer: &mut Explorer,
+    current_year: u32,
+    prefix: &TablePrefix,
+    table_id: &str,
+    suffix: &Option<String>,
+    ) -> Result<Option<String>>
+{
+    let records = explorer.describe_table(prefix, table_id, suffix)?;
+
+    if records.is_empty() {
+        return Ok(None);
+    }
+
+    let information = table_information(explorer, current_year, prefix, table_id, suffix, &records)?;
+    let mut out = format_describe_table_pretty(current_year, records, false);
+    out.push_str(&information);
+
+    Ok(Some(out))
+}
+
+/// Table name, and a grid of the estimates and years each variant of
+/// the table is available for. New versions are marked for the table
+/// described, from its `records`.
+pub fn table_information(
+    explorer: &mut Explorer,
+    current_year: u32,
+    prefix: &TablePrefix,
+    table_id: &str,
+    suffix: &Option<String>,
+    records: &[VariableRecord],
+    ) -> Result<String>
+{
+    let mut out = "Table Information:\n============================================\n\n".to_owned();
+
+    let table_info = explorer.query_by_table_id(
+        &Some(prefix.clone()),
+        table_id,
+        suffix,
+    )?;
+    if let Some(table_record) = table_info.get(0) {
+        out.push_str(&format_table_name(&table_record));
+        out.push_str("\n");
+    }
+
+    let starts = version_starts(&get_table_versions(current_year, records.to_vec()));
+
+    // B and C, and all suffixes, since they're often checked together
+    let mut variants = Vec::new();
+    for (code, est_years) in explorer.query_table_id_variant_est_years(table_id)? {
+        let is_described = code.prefix == *prefix &&
+            code.table_id == table_id &&
+            code.suffix == *suffix;
+
+        variants.push(VariantAvailability {
+            code: code,
+            est_years: est_years,
+            version_starts: if is_described { starts.clone() } else { Vec::new() },
+        });
+    }
+    out.push_str(&format_availability_grid(&variants));
+
+    Ok(out)
+}
+
+/// Summary of how C columns roll up from B columns, as stored at
+/// refresh, for the latest year both tables are in (five-year estimate
+/// if both estimates are).
+///
+/// Returns None if there aren't both B and C tables in `vintage`.
+pub fn describe_rollup_summary(
+    explorer: &mut Explorer,
+    table_id: &str,
+    suffix: &Option<String>,
+    vintage: &Vintage,
+    grep: &Option<String>,
+    ) -> Result<Option<String>>
+{
+    let (b_records, c_records) = rollup_records(explorer, table_id, suffix, vintage)?;
+    let stored = explorer.query_rollups(table_id, suffix)?;
+
+    let latest = shared_vintages(&b_records, &c_records).into_iter()
+        .filter(|vintage| stored.contains_key(vintage))
+        .map(|(estimate, year)| (year, estimate))
+        .max();
+
+    let (year, estimate) = match latest {
+        Some(latest) => latest,
+        None => return Ok(None),
+    };
+
+    let (b_columns, rollups) = vintage_rollup(
+        &b_records,
+        &c_records,
+        &stored[&(estimate.clone(), year)],
+        &estimate,
+        year,
+        grep,
+    );
+
+    Ok(Some(format_rollup(
+        &b_columns,
+        &rollups,
+        &format!("{} {}", estimate.short_name(), year),
+    )))
+}
+
+/// B and C records for a table id, in `vintage`. Not grepped, since
+/// the stored roll up refers to all columns.
+fn rollup_records(
+    explorer: &mut Explorer,
+    table_id: &str,
+    suffix: &Option<String>,
+    vintage: &Vintage,
+    ) -> Result<(Vec<VariableRecord>, Vec<VariableRecord>)>
+{
+    let mut b_records = explorer.describe_table(&TablePrefix::B, table_id, suffix)?;
+    let mut c_records = explorer.describe_table(&TablePrefix::C, table_id, suffix)?;
+    b_records.retain(|record| vintage.contains(record));
+    c_records.retain(|record| vintage.contains(record));
+
+    Ok((b_records, c_records))
+}
+
+/// Every suffix variant of a table, and how each compares to the base
+/// table.
+pub fn describe_family(
+    explorer: &mut Explorer,
+    current_year: u32,
+    prefix: &TablePrefix,
+    table_id: &str,
+    ) -> Result<String>
+{
+    // no suffix matches all suffixes
+    let mut tables = explorer.query_by_table_id(&Some(prefix.clone()), table_id, &None)?;
+    tables.sort();
+
+    let mut members = Vec::new();
+    for table in tables {
+        let est_years = explorer.query_est_years(
+            &table.code.prefix,
+            &table.code.table_id,
+            &table.code.suffix,
+        )?;
+        let records = explorer.describe_table(
+            &table.code.prefix,
+            &table.code.table_id,
+            &table.code.suffix,
+        )?;
+
+        let versions = get_table_versions(current_year, records);
+        let columns = latest_version(&versions)
+            .map(|version| {
+                table_columns(&version.records).into_iter()
+                    .filter(|column| column.code.var_type == VariableType::Value)
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new);
+
+        members.push(FamilyMember {
+            table: table,
+            est_years: est_years,
+            columns: columns,
+        });
+    }
+
+    Ok(format_table_family(&members))
+}
+
+/// A table that isn't found, with suggestions of tables that are
+pub fn table_not_found(explorer: &mut Explorer, code: TableCode) -> Result<Unavailable> {
+    let all_codes = explorer.table_codes()?;
+    let suggestions = suggest(&Some(code.prefix.clone()), &code.table_id, &code.suffix, &all_codes);
+    Ok(Unavailable::NotFound(code, suggestions))
+}
+
+/// Whether any table with this prefix and id exists, with any suffix
+fn has_family(explorer: &mut Explorer, code: &TableCode) -> Result<bool> {
+    let tables = explorer.query_by_table_id(&Some(code.prefix.clone()), &code.table_id, &None)?;
+    Ok(!tables.is_empty())
+}
+
+/// Whether the table exists, without loading its variables
+fn has_table(explorer: &mut Explorer, code: &TableCode) -> Result<bool> {
+    // no suffix matches all suffixes, so compare suffix too
+    let tables = explorer.query_by_table_id(&Some(code.prefix.clone()), &code.table_id, &code.suffix)?;
+    Ok(tables.iter().any(|table| table.code.suffix == code.suffix))
+}
+
+/// Table code and label
+fn table_record(
+    explorer: &mut Explorer,
+    prefix: &TablePrefix,
+    table_id: &str,
+    suffix: &Option<String>,
+    ) -> Result<TableRecord>
+{
+    let table = explorer.query_by_table_id(
+        &Some(prefix.clone()),
+        table_id,
+        suffix,
+    )?
+    .into_iter()
+    .next()
+    // table label may be missing if db is in a bad state,
+    // but code is still known.
+    .unwrap_or_else(|| TableRecord {
+        code: TableCode {
+            prefix: prefix.clone(),
+            table_id: table_id.to_owned(),
+            suffix: suffix.clone(),
+        },
+        label: String::new(),
+    });
+    Ok(table)
+}
+
+/// Structured table description, see `TableDescription`
+pub fn describe_json(
+    explorer: &mut Explorer,
+    current_year: u32,
+    prefix: &TablePrefix,
+    table_id: &str,
+    suffix: &Option<String>,
+    records: Vec<VariableRecord>,
+    ) -> Result<JsonValue>
+{
+    describe_description(explorer, current_year, prefix, table_id, suffix, records)?
+        .to_json()
+}
+
+/// Description shared by the json and template formats
+fn describe_description(
+    explorer: &mut Explorer,
+    current_year: u32,
+    prefix: &TablePrefix,
+    table_id: &str,
+    suffix: &Option<String>,
+    records: Vec<VariableRecord>,
+    ) -> Result<TableDescription>
+{
+    let table = table_record(explorer, prefix, table_id, suffix)?;
+
+    let universe = explorer.query_universe(prefix, table_id, suffix)?;
+    let est_years = explorer.query_est_years(prefix, table_id, suffix)?;
+
+    Ok(table_description(
+        current_year,
+        &table,
+        universe,
+        &est_years,
+        records,
+    ))
+}
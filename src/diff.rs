@@ -0,0 +1,252 @@
+// Column-level changes between consecutive versions of a table.
+//
+// Columns are matched by label path first, so that a column which
+// moves from 005 to 006 is reported as a move and not as a removal
+// plus an addition. Unmatched columns that keep their id are
+// relabelled; anything left over is added or removed.
+
+use acs::{
+    Estimate,
+    TableColumn,
+    TableVersion,
+    VariableRecord,
+    VariableType,
+    format_version_estimates,
+    get_table_versions,
+    table_columns,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnChange {
+    Added(TableColumn),
+    Removed(TableColumn),
+    /// Same label, different column id
+    Moved {
+        old: TableColumn,
+        new: TableColumn,
+    },
+    /// Same column id, different label
+    Relabelled {
+        old: TableColumn,
+        new: TableColumn,
+    },
+}
+
+/// Label path used for matching, ignores case.
+pub fn path_key(column: &TableColumn) -> String {
+    column.path.iter()
+        .map(|level| level.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("!!")
+}
+
+/// Pairs of (old index, new index) for columns with the same label
+/// path, in order of old columns. Each column is matched at most once.
+pub fn match_by_path(old: &[TableColumn], new: &[TableColumn]) -> Vec<(usize, usize)> {
+    let new_keys: Vec<String> = new.iter().map(path_key).collect();
+    let mut new_matched = vec![false; new.len()];
+
+    let mut res = Vec::new();
+    for (i, old_column) in old.iter().enumerate() {
+        let key = path_key(old_column);
+
+        let found = new_keys.iter().enumerate()
+            .position(|(j, new_key)| !new_matched[j] && *new_key == key);
+
+        if let Some(j) = found {
+            new_matched[j] = true;
+            res.push((i, j));
+        }
+    }
+    res
+}
+
+/// Changes from old to new columns. Unchanged columns are left out.
+pub fn diff_columns(old: &[TableColumn], new: &[TableColumn]) -> Vec<ColumnChange> {
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+    let mut changes = Vec::new();
+
+    for (i, j) in match_by_path(old, new) {
+        old_matched[i] = true;
+        new_matched[j] = true;
+
+        if old[i].code.column_id != new[j].code.column_id {
+            changes.push(ColumnChange::Moved {
+                old: old[i].clone(),
+                new: new[j].clone(),
+            });
+        }
+    }
+
+    // leftover columns which kept their id
+    for (i, old_column) in old.iter().enumerate() {
+        if old_matched[i] {
+            continue;
+        }
+        let found = new.iter().enumerate().position(|(j, new_column)| {
+            !new_matched[j] && new_column.code.column_id == old_column.code.column_id
+        });
+        if let Some(j) = found {
+            old_matched[i] = true;
+            new_matched[j] = true;
+            changes.push(ColumnChange::Relabelled {
+                old: old_column.clone(),
+                new: new[j].clone(),
+            });
+        }
+    }
+
+    for (i, old_column) in old.iter().enumerate() {
+        if !old_matched[i] {
+            changes.push(ColumnChange::Removed(old_column.clone()));
+        }
+    }
+    for (j, new_column) in new.iter().enumerate() {
+        if !new_matched[j] {
+            changes.push(ColumnChange::Added(new_column.clone()));
+        }
+    }
+
+    changes
+}
+
+/// Pairs of (old, new) version indices where one version follows
+/// another within the same estimate.
+pub fn version_boundaries(versions: &[TableVersion]) -> Vec<(usize, usize)> {
+    let mut res = Vec::new();
+
+    for estimate in &[Estimate::FiveYear, Estimate::OneYear] {
+        // (min year for this estimate, version index)
+        let mut est_versions: Vec<(u32, usize)> = versions.iter().enumerate()
+            .filter_map(|(i, version)| {
+                version.estimates.iter()
+                    .find(|&&(ref e, _, _)| e == estimate)
+                    .map(|&(_, min_year, _)| (min_year, i))
+            })
+            .collect();
+        est_versions.sort();
+
+        for pair in est_versions.windows(2) {
+            let boundary = (pair[0].1, pair[1].1);
+            if !res.contains(&boundary) {
+                res.push(boundary);
+            }
+        }
+    }
+    res
+}
+
+/// For each version boundary, lists columns added, removed, moved
+/// and relabelled. Only estimate columns are compared, margin of error
+/// columns follow them.
+pub fn format_describe_table_diff(current_year: u32, records: Vec<VariableRecord>) -> String {
+    let table_code = match records.get(0) {
+        Some(record) => record.code.table_code.clone(),
+        None => return String::new(),
+    };
+
+    let versions = get_table_versions(current_year, records);
+
+    let mut res = format!("\nTable {} Changes:\n============================================\n\n",
+        table_code,
+    );
+
+    let boundaries = version_boundaries(&versions);
+
+    if boundaries.is_empty() {
+        res.push_str("No changes, only one version of table per estimate\n");
+        return res;
+    }
+
+    let value_columns: Vec<Vec<TableColumn>> = versions.iter()
+        .map(|version| {
+            table_columns(&version.records).into_iter()
+                .filter(|column| column.code.var_type == VariableType::Value)
+                .collect()
+        })
+        .collect();
+
+    for (i, j) in boundaries {
+        res.push_str(&format!("{} -> {}\n",
+            format_version_estimates(&versions[i]),
+            format_version_estimates(&versions[j]),
+        ));
+        res.push_str("-----------+-----------+------------------------\n");
+
+        let changes = diff_columns(&value_columns[i], &value_columns[j]);
+
+        if changes.is_empty() {
+            // e.g. only margin of error columns changed
+            res.push_str("no column changes\n");
+        }
+
+        for change in changes {
+            let line = match change {
+                ColumnChange::Added(column) => {
+                    format!("added      |     -> {} | {}",
+                        column.code.column_id,
+                        column.path.join(" > "),
+                    )
+                },
+                ColumnChange::Removed(column) => {
+                    format!("removed    | {} ->     | {}",
+                        column.code.column_id,
+                        column.path.join(" > "),
+                    )
+                },
+                ColumnChange::Moved { old, new } => {
+                    format!("moved      | {} -> {} | {}",
+                        old.code.column_id,
+                        new.code.column_id,
+                        new.path.join(" > "),
+                    )
+                },
+                ColumnChange::Relabelled { old, new } => {
+                    format!("relabelled | {} -> {} | {}\n           |           | -> {}",
+                        old.code.column_id,
+                        new.code.column_id,
+                        old.path.join(" > "),
+                        new.path.join(" > "),
+                    )
+                },
+            };
+            res.push_str(&line);
+            res.push_str("\n");
+        }
+        res.push_str("\n");
+    }
+
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acs::fixtures::column;
+
+    #[test]
+    fn test_diff_columns() {
+        let old = vec![
+            column("B25102_001E", &["Total"], false),
+            column("B25102_002E", &["Total", "$1,500 or more"], true),
+            column("B25102_003E", &["Total", "No taxes paid"], true),
+            column("B25102_004E", &["Total", "Not mortgaged"], true),
+        ];
+        let new = vec![
+            column("B25102_001E", &["Total"], false),
+            column("B25102_002E", &["Total", "$1,500 to $1,999"], true),
+            column("B25102_003E", &["Total", "$2,000 or more"], true),
+            column("B25102_004E", &["Total", "No taxes paid"], true),
+        ];
+
+        let changes = diff_columns(&old, &new);
+
+        assert_eq!(changes, vec![
+            ColumnChange::Moved { old: old[2].clone(), new: new[3].clone() },
+            ColumnChange::Relabelled { old: old[1].clone(), new: new[1].clone() },
+            ColumnChange::Removed(old[3].clone()),
+            ColumnChange::Added(new[2].clone()),
+        ]);
+    }
+}
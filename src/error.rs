@@ -1,5 +1,9 @@
+use handlebars;
 use reqwest;
 use rusqlite;
+use rustyline;
+use serde_json;
+use serde_yaml;
 
 error_chain! {
     foreign_links {
@@ -7,5 +11,10 @@ error_chain! {
         Reqwest(reqwest::Error);
         ReqwestUrl(reqwest::UrlError);
         Rusqlite(rusqlite::Error);
+        Readline(rustyline::error::ReadlineError);
+        Json(serde_json::Error);
+        Yaml(serde_yaml::Error);
+        Template(handlebars::TemplateError);
+        TemplateRender(handlebars::RenderError);
     }
 }
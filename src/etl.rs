@@ -0,0 +1,262 @@
+// ETL configs for loading a table into a database, one yaml document
+// per version of the table.
+//
+// The config is built as an `EtlConfig` and serialized with serde_yaml,
+// so labels with quotes or colons don't break the output.
+//
+// Dimensions are inferred from the label hierarchy: each level of the
+// "!!" path below Total is a dimension, and the labels at that level are
+// its members. A column's members are the levels of its own path, so
+// subtotal columns have fewer members than leaves.
+
+use acs::{
+    VariableRecord,
+    VariableType,
+    get_table_versions,
+    label_path,
+};
+use error::*;
+
+use serde_yaml;
+use std::collections::BTreeMap;
+
+const TAG: &str = "acs";
+// used when the table has no universe in the db
+const DEFAULT_VALUE_LABEL: &str = "population";
+// levels of the hierarchy have no names, so dimensions are numbered
+const DIMENSION_LABEL_PREFIX: &str = "dimension_";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EtlConfig {
+    pub name: String,
+    pub tag: String,
+    pub min_year: u32,
+    pub max_year: u32,
+    /// estimate short name -> (min year, max year)
+    pub estimates: BTreeMap<String, (u32, u32)>,
+    pub acs_table: AcsTable,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AcsTable {
+    pub id: String,
+    pub value_label: String,
+    pub dimension_labels: Vec<String>,
+    /// dimension label -> members, in order of first appearance
+    pub dimension_members: BTreeMap<String, Vec<String>>,
+    /// column id -> column
+    pub columns: BTreeMap<String, EtlColumn>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EtlColumn {
+    pub name: String,
+    /// member of each dimension, from the top of the hierarchy
+    pub members: Vec<String>,
+}
+
+/// One config per version of the table. `name` is the table label, and
+/// `universe` becomes the value label.
+pub fn etl_configs(
+    current_year: u32,
+    records: Vec<VariableRecord>,
+    etl_config_all: bool,
+    name: &str,
+    universe: Option<String>,
+    ) -> Vec<EtlConfig>
+{
+    let versions = get_table_versions(current_year, records);
+    let value_label = universe.unwrap_or_else(|| DEFAULT_VALUE_LABEL.to_owned());
+
+    let mut res = Vec::new();
+
+    for table_version in versions {
+        let mut records = table_version.records;
+        // Figure out better way to trim?
+        if records.len() == 2 {
+            records[1].label = records[1].label.trim_right_matches(":").to_owned();
+        }
+
+        let table_id = records[0].code.table_code.prefix.to_string() +
+            &records[0].code.table_code.table_id;
+
+        let min_year = table_version.min_year;
+
+        let estimates = table_version.estimates.iter()
+            .map(|&(ref estimate, est_min_year, est_max_year)| {
+                (estimate.short_name().to_owned(), (est_min_year, est_max_year))
+            })
+            .collect();
+
+        let records: Vec<VariableRecord> = records.into_iter()
+            .filter(|record| {
+                record.code.var_type == VariableType::Value &&
+                (etl_config_all || !record.label.ends_with(':'))
+            })
+            .collect();
+
+        let mut dimension_labels = Vec::new();
+        let mut dimension_members: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut columns = BTreeMap::new();
+
+        for record in records {
+            let path = label_path(&record.label);
+            let name = column_name(&path);
+            let members = column_members(path);
+
+            for (i, member) in members.iter().enumerate() {
+                if i == dimension_labels.len() {
+                    dimension_labels.push(format!("{}{}", DIMENSION_LABEL_PREFIX, i + 1));
+                }
+                let dimension = dimension_members.entry(dimension_labels[i].clone())
+                    .or_insert_with(Vec::new);
+                if !dimension.contains(member) {
+                    dimension.push(member.clone());
+                }
+            }
+
+            let column = EtlColumn {
+                name: name,
+                members: members,
+            };
+            columns.insert(record.code.column_id, column);
+        }
+
+        res.push(EtlConfig {
+            name: name.to_owned(),
+            tag: TAG.to_owned(),
+            min_year: min_year,
+            max_year: table_version.max_year,
+            estimates: estimates,
+            acs_table: AcsTable {
+                id: table_id,
+                value_label: value_label.clone(),
+                dimension_labels: dimension_labels,
+                dimension_members: dimension_members,
+                columns: columns,
+            },
+        });
+    }
+    res
+}
+
+/// Yaml documents for each config, separated by "---".
+pub fn format_etl_configs(configs: &[EtlConfig]) -> Result<String> {
+    let mut docs = Vec::new();
+    for config in configs {
+        let doc = serde_yaml::to_string(config)?;
+        // the separator is added when joining
+        let doc = doc.trim_left_matches("---\n").to_owned();
+        docs.push(doc + "\n");
+    }
+    Ok(docs.join("---\n"))
+}
+
+/// Levels of the label path below Total; none for the Total column
+fn column_members(path: Vec<String>) -> Vec<String> {
+    if path.len() == 1 && path[0].to_lowercase() == "total" {
+        Vec::new()
+    } else {
+        path
+    }
+}
+
+/// Camel-cased levels of the label path, joined by "_". `label_path`
+/// already strips "Estimate", "Total" and colons for every year's format.
+fn column_name(path: &[String]) -> String {
+    path.iter()
+        .map(|level| to_camelcase(&level.replace("'", "")))
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn to_camelcase(s: &str) -> String {
+    s.split_whitespace().map(|word| {
+        let mut c = word.chars();
+
+        match c.next() {
+            Some(f) => f.to_uppercase().collect::<String>() + c.as_str(),
+            None => String::new(),
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acs::Estimate;
+    use acs::fixtures::record;
+
+    #[test]
+    fn test_etl_configs() {
+        let records = vec![
+            record("B25102_001E", "Estimate!!Total", 2017, Estimate::FiveYear),
+            record("B25102_002E", "Estimate!!Total!!Owner's mortgage", 2017, Estimate::FiveYear),
+            record("B25102_003E", "Estimate!!Total!!Not mortgaged: \"free & clear\"", 2017, Estimate::FiveYear),
+        ];
+
+        let configs = etl_configs(2018, records, false, "Mortgage Status", Some("Owner-occupied housing units".to_owned()));
+        assert_eq!(configs.len(), 1);
+
+        let config = &configs[0];
+        assert_eq!(config.name, "Mortgage Status");
+        assert_eq!(config.acs_table.id, "B25102");
+        assert_eq!(config.acs_table.value_label, "Owner-occupied housing units");
+        assert_eq!(config.estimates.get("5yr"), Some(&(2017, 2017)));
+        assert_eq!(config.acs_table.columns["002"], EtlColumn {
+            name: "OwnersMortgage".to_owned(),
+            members: vec!["Owner's mortgage".to_owned()],
+        });
+
+        let configs = etl_configs(2018, vec![record("B25102_001E", "Estimate!!Total", 2017, Estimate::FiveYear)], false, "", None);
+        assert_eq!(configs[0].acs_table.value_label, "population");
+    }
+
+    #[test]
+    fn test_etl_configs_dimensions() {
+        let records = vec![
+            record("B25102_001E", "Estimate!!Total:", 2019, Estimate::FiveYear),
+            record("B25102_002E", "Estimate!!Total:!!With a mortgage:", 2019, Estimate::FiveYear),
+            record("B25102_003E", "Estimate!!Total:!!With a mortgage:!!Less than $800", 2019, Estimate::FiveYear),
+            record("B25102_004E", "Estimate!!Total:!!With a mortgage:!!$800 or more", 2019, Estimate::FiveYear),
+            record("B25102_005E", "Estimate!!Total:!!Not mortgaged:", 2019, Estimate::FiveYear),
+            record("B25102_006E", "Estimate!!Total:!!Not mortgaged:!!Less than $800", 2019, Estimate::FiveYear),
+            record("B25102_007E", "Estimate!!Total:!!Not mortgaged:!!$800 or more", 2019, Estimate::FiveYear),
+        ];
+
+        let configs = etl_configs(2020, records, true, "Mortgage Status", None);
+        let table = &configs[0].acs_table;
+
+        assert_eq!(table.dimension_labels, vec!["dimension_1", "dimension_2"]);
+        assert_eq!(table.dimension_members["dimension_1"], vec!["With a mortgage", "Not mortgaged"]);
+        assert_eq!(table.dimension_members["dimension_2"], vec!["Less than $800", "$800 or more"]);
+
+        assert_eq!(table.columns["001"].name, "Total");
+        assert_eq!(table.columns["003"].name, "WithAMortgage_LessThan$800");
+        assert_eq!(table.columns["006"].name, "NotMortgaged_LessThan$800");
+
+        assert!(table.columns["001"].members.is_empty());
+        assert_eq!(table.columns["005"].members, vec!["Not mortgaged"]);
+        assert_eq!(table.columns["006"].members, vec!["Not mortgaged", "Less than $800"]);
+    }
+
+    #[test]
+    fn test_format_etl_configs_round_trip() {
+        let records = vec![
+            record("B25102_001E", "Total:", 2012, Estimate::FiveYear),
+            record("B25102_002E", "Total:!!With a mortgage", 2012, Estimate::FiveYear),
+            record("B25102_001E", "Estimate!!Total", 2017, Estimate::FiveYear),
+            record("B25102_002E", "Estimate!!Total!!With a mortgage: \"first\" lien", 2017, Estimate::FiveYear),
+        ];
+
+        let configs = etl_configs(2018, records, true, "Mortgage Status: Owners", None);
+        assert_eq!(configs.len(), 2);
+
+        let yaml = format_etl_configs(&configs).unwrap();
+        let parsed: Vec<EtlConfig> = yaml.split("---\n")
+            .map(|doc| serde_yaml::from_str(doc).unwrap())
+            .collect();
+
+        assert_eq!(parsed, configs);
+    }
+}
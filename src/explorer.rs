@@ -1,11 +1,12 @@
 use acs::*;
 use error::*;
+use rollup::{StoredRollup, rollup, shared_vintages, vintage_columns};
 
 use json;
 use reqwest;
 use reqwest::{StatusCode, Url};
 use rusqlite;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Read;
 use std::ops::Range;
 use std::path::PathBuf;
@@ -48,6 +49,11 @@ use time;
 
 const CENSUS_URL_BASE: &str = "https://api.census.gov/data/";
 const VARS_URL: &str = "variables.json";
+const GROUPS_URL: &str = "groups.json";
+// fts5 puts these around matched terms. Control characters, so they
+// can't be confused with anything in a label.
+const MATCH_START: &str = "\x02";
+const MATCH_END: &str = "\x03";
 
 pub struct Explorer {
     http_client: reqwest::Client,
@@ -83,7 +89,8 @@ impl Explorer {
                 prefix TEXT NOT NULL,
                 table_id TEXT NOT NULL,
                 suffix TEXT,
-                label TEXT NOT NULL
+                label TEXT NOT NULL,
+                universe TEXT
             );
             DROP TABLE IF EXISTS acs_vars;
             CREATE TABLE acs_vars (
@@ -106,6 +113,17 @@ impl Explorer {
                 estimate TEXT NOT NULL,
                 year INTEGER NOT NULL
             );
+            DROP TABLE IF EXISTS acs_rollups;
+            CREATE TABLE acs_rollups (
+                id INTEGER PRIMARY KEY ASC,
+                table_id TEXT NOT NULL,
+                suffix TEXT,
+                estimate TEXT NOT NULL,
+                year INTEGER NOT NULL,
+                c_column_id TEXT NOT NULL,
+                b_column_ids TEXT NOT NULL,
+                method TEXT
+            );
             ",
         ).chain_err(|| "Error prepping db")?;
 
@@ -116,6 +134,7 @@ impl Explorer {
             .chain_err(|| "Error switching journal mode to Memory")?;
 
         let mut table_map = HashMap::new();
+        let mut universe_map = HashMap::new();
 
         for year in years {
             for acs_est in acs_estimates {
@@ -123,6 +142,7 @@ impl Explorer {
                     year,
                     &acs_est,
                     &mut table_map,
+                    &mut universe_map,
                 ) {
                     Ok(_) => println!("completed refresh {}-{}", year, acs_est),
                     Err(err) => println!("no refresh {}-{}: {}", year, acs_est, err),
@@ -140,9 +160,10 @@ impl Explorer {
                         prefix,
                         table_id,
                         suffix,
-                        label
+                        label,
+                        universe
                     ) VALUES (
-                        ?1, ?2, ?3, ?4
+                        ?1, ?2, ?3, ?4, ?5
                     )"
                 ).chain_err(|| "Error preparing acs_tables insert")?;
 
@@ -152,6 +173,7 @@ impl Explorer {
                         &code.table_id,
                         &code.suffix,
                         label,
+                        &universe_map.get(code),
                     ]
                 ).chain_err(|| "Error executing acs_tables insert")?;
 
@@ -171,9 +193,14 @@ impl Explorer {
             CREATE INDEX acs_tables_id_idx on acs_tables (table_id, prefix, suffix);
             CREATE INDEX acs_tables_est_years_idx on acs_est_years (table_id, prefix, suffix);
         ").chain_err(|| "Error creating indexes")?;
+
+        self.refresh_rollups()?;
         self.db_client.execute_batch("
             DROP TABLE IF EXISTS acs_fts;
-            CREATE VIRTUAL TABLE acs_fts USING fts5(prefix, table_id, suffix, label);
+            CREATE VIRTUAL TABLE acs_fts USING fts5(
+                prefix, table_id, suffix, label,
+                tokenize = 'porter unicode61'
+            );
         ")
             .chain_err(|| "Error creating fulltext search table")?;
         self.db_client.execute_batch("
@@ -183,6 +210,94 @@ impl Explorer {
         ")
             .chain_err(|| "Error populating fulltext search table")?;
 
+        // synonyms are user-edited, so they survive a refresh
+        self.ensure_synonyms()?;
+
+        Ok(())
+    }
+
+    /// Infers how each C table rolls up from its B table, for every
+    /// year and estimate both are in, and stores it in acs_rollups.
+    fn refresh_rollups(&mut self) -> Result<()> {
+        let pairs = {
+            let mut query = self.db_client.prepare("
+                SELECT DISTINCT c.table_id, c.suffix
+                    FROM acs_tables c
+                    JOIN acs_tables b
+                        ON b.table_id = c.table_id AND b.suffix IS c.suffix
+                    WHERE c.prefix = 'C' AND b.prefix = 'B'
+            ")?;
+            let rows = query.query_map(&[], |row| {
+                let pair: (String, Option<String>) = (row.get(0), row.get(1));
+                pair
+            })?;
+
+            let mut res = Vec::new();
+            for row in rows {
+                res.push(row?);
+            }
+            res
+        };
+
+        let mut rows = Vec::new();
+        for (table_id, suffix) in pairs {
+            let b_records = self.describe_table(&TablePrefix::B, &table_id, &suffix)?;
+            let c_records = self.describe_table(&TablePrefix::C, &table_id, &suffix)?;
+
+            for (estimate, year) in shared_vintages(&b_records, &c_records) {
+                let b_columns = vintage_columns(&b_records, &estimate, year);
+                let c_columns = vintage_columns(&c_records, &estimate, year);
+
+                for rollup in rollup(&b_columns, &c_columns) {
+                    rows.push((
+                        table_id.clone(),
+                        suffix.clone(),
+                        estimate.clone(),
+                        year,
+                        StoredRollup::from(&rollup),
+                    ));
+                }
+            }
+        }
+
+        let db_tx = self.db_client.transaction()?;
+
+        for &(ref table_id, ref suffix, ref estimate, year, ref stored) in &rows {
+            let mut insert = db_tx.prepare_cached(
+                "INSERT INTO acs_rollups (
+                    table_id,
+                    suffix,
+                    estimate,
+                    year,
+                    c_column_id,
+                    b_column_ids,
+                    method
+                ) VALUES (
+                    ?1, ?2, ?3, ?4, ?5, ?6, ?7
+                )"
+            ).chain_err(|| "Error preparing acs_rollups insert")?;
+
+            insert.execute(
+                &[
+                    table_id,
+                    suffix,
+                    estimate,
+                    &year,
+                    &stored.c_column_id,
+                    &stored.b_column_ids.join(","),
+                    &stored.method,
+                ]
+            ).chain_err(|| "Error executing acs_rollups insert")?;
+        }
+
+        db_tx.commit()?;
+
+        self.db_client.execute_batch("
+            CREATE INDEX acs_rollups_id_idx on acs_rollups (table_id, suffix);
+        ").chain_err(|| "Error creating roll up index")?;
+
+        println!("{} roll up columns", rows.len());
+
         Ok(())
     }
 
@@ -191,11 +306,12 @@ impl Explorer {
         year: usize,
         acs_est: &Estimate,
         table_map: &mut HashMap<TableCode, String>,
+        universe_map: &mut HashMap<TableCode, String>,
         ) -> Result<()>
     {
         // TODO check year
         let start = time::precise_time_s();
-        let acs_vars_data = self.fetch_acs_combination(year, acs_est)?;
+        let acs_vars_data = self.fetch_acs_combination(year, acs_est, VARS_URL)?;
         let end = time::precise_time_s();
         println!("Fetch time for {}-{}: {}", year, acs_est, end - start);
         let start = time::precise_time_s();
@@ -208,13 +324,25 @@ impl Explorer {
         let end = time::precise_time_s();
         println!("Process time for {}-{}: {}", year, acs_est, end - start);
 
+        // Universe is only in groups, which aren't available for
+        // every year. Not having it shouldn't fail the refresh.
+        match self.fetch_acs_combination(year, acs_est, GROUPS_URL) {
+            Ok(groups_data) => {
+                if let Err(err) = process_acs_groups_data(&groups_data, universe_map) {
+                    println!("no universes {}-{}: {}", year, acs_est, err);
+                }
+            },
+            Err(err) => println!("no universes {}-{}: {}", year, acs_est, err),
+        }
+
         res
     }
 
     fn fetch_acs_combination(
         &self,
         year: usize,
-        acs_est: &Estimate
+        acs_est: &Estimate,
+        endpoint: &str,
         ) -> Result<String>
     {
         // TODO check year
@@ -226,7 +354,7 @@ impl Explorer {
         if year >= 2016 {
             url = url.join("acs/")?;
         }
-        url = url.join(acs_est.url_frag())?.join(VARS_URL)?;
+        url = url.join(acs_est.url_frag())?.join(endpoint)?;
         //println!("{}", url);
 
         let mut resp = self.http_client.get(url).send()?;
@@ -377,6 +505,42 @@ impl Explorer {
         Ok(())
     }
 
+    pub fn query_universe(
+        &mut self,
+        prefix: &TablePrefix,
+        table_id: &str,
+        suffix: &Option<String>,
+        ) -> Result<Option<String>>
+    {
+        let sql_str = "
+            SELECT universe
+            from acs_tables
+            where table_id = ?1 and prefix = ?2 and suffix
+        ";
+
+        let mut res = None;
+
+        // duplication just to handle putting in the right number of
+        // args
+        if !suffix.is_none() {
+            let sql_str = format!("{} {};", sql_str, "= ?3");
+            let mut query = self.db_client.prepare(&sql_str)?;
+            let rows = query.query_map(&[&table_id, prefix, suffix], |row| row.get(0))?;
+            for row in rows {
+                res = row?;
+            }
+        } else {
+            let sql_str = format!("{} {};", sql_str, "is null");
+            let mut query = self.db_client.prepare(&sql_str)?;
+            let rows = query.query_map(&[&table_id, prefix], |row| row.get(0))?;
+            for row in rows {
+                res = row?;
+            }
+        }
+
+        Ok(res)
+    }
+
     pub fn query_by_table_id(
         &mut self,
         prefix: &Option<TablePrefix>,
@@ -488,6 +652,28 @@ impl Explorer {
         }
     }
 
+    /// All table codes, e.g. for completion
+    pub fn table_codes(&mut self) -> Result<Vec<TableCode>> {
+        let mut query = self.db_client.prepare("
+            SELECT prefix, table_id, suffix
+                FROM acs_tables
+                ORDER BY table_id, prefix, suffix
+        ")?;
+        let codes = query.query_map(&[], |row| {
+            TableCode {
+                prefix: row.get(0),
+                table_id: row.get(1),
+                suffix: row.get(2),
+            }
+        })?;
+
+        let mut res = Vec::new();
+        for code in codes {
+            res.push(code?);
+        }
+        Ok(res)
+    }
+
     pub fn describe_table(
         &mut self,
         prefix: &TablePrefix,
@@ -650,28 +836,241 @@ impl Explorer {
 
     }
 
+    /// Creates the synonyms table and fills it with defaults, only if
+    /// it doesn't exist yet; otherwise user edits would be overwritten.
+    fn ensure_synonyms(&mut self) -> Result<()> {
+        let exists: i64 = self.db_client.query_row(
+            "SELECT count(*) FROM sqlite_master
+                WHERE type = 'table' AND name = 'acs_synonyms'",
+            &[],
+            |row| row.get(0)
+        )?;
+
+        if exists > 0 {
+            return Ok(());
+        }
+
+        self.db_client.execute_batch("
+            CREATE TABLE acs_synonyms (
+                id INTEGER PRIMARY KEY ASC,
+                term TEXT NOT NULL,
+                synonym TEXT NOT NULL,
+                UNIQUE (term, synonym)
+            );
+        ").chain_err(|| "Error creating synonyms table")?;
+
+        let db_tx = self.db_client.transaction()?;
+        for &(term, synonym) in DEFAULT_SYNONYMS {
+            let mut insert = db_tx.prepare_cached(
+                "INSERT OR IGNORE INTO acs_synonyms (term, synonym)
+                    VALUES (?1, ?2)"
+            ).chain_err(|| "Error preparing acs_synonyms insert")?;
+
+            insert.execute(&[&term, &synonym])
+                .chain_err(|| "Error executing acs_synonyms insert")?;
+        }
+        db_tx.commit()?;
+
+        Ok(())
+    }
+
+    pub fn synonyms(&mut self) -> Result<Vec<(String, String)>> {
+        self.ensure_synonyms()?;
+
+        let mut query = self.db_client.prepare("
+            SELECT term, synonym
+                FROM acs_synonyms
+                ORDER BY term, synonym
+        ")?;
+        let rows = query.query_map(&[], |row| {
+            (row.get(0), row.get(1))
+        })?;
+
+        let mut res = Vec::new();
+        for row in rows {
+            res.push(row?);
+        }
+        Ok(res)
+    }
+
+    pub fn add_synonym(&mut self, term: &str, synonym: &str) -> Result<()> {
+        self.ensure_synonyms()?;
+
+        self.db_client.execute(
+            "INSERT OR IGNORE INTO acs_synonyms (term, synonym)
+                VALUES (?1, ?2)",
+            &[&term.to_lowercase(), &synonym.to_lowercase()]
+        ).chain_err(|| "Error adding synonym")?;
+
+        Ok(())
+    }
+
+    /// Returns number of synonyms removed
+    pub fn remove_synonym(&mut self, term: &str, synonym: &str) -> Result<i32> {
+        self.ensure_synonyms()?;
+
+        // synonyms are symmetric, so remove either direction
+        let removed = self.db_client.execute(
+            "DELETE FROM acs_synonyms
+                WHERE (term = ?1 AND synonym = ?2)
+                    OR (term = ?2 AND synonym = ?1)",
+            &[&term.to_lowercase(), &synonym.to_lowercase()]
+        ).chain_err(|| "Error removing synonym")?;
+
+        Ok(removed)
+    }
+
+    /// Estimates and years for all variants of a table id, regardless
+    /// of prefix or suffix.
+    pub fn query_table_id_est_years(
+        &mut self,
+        table_id: &str,
+        ) -> Result<HashMap<Estimate, Vec<u32>>>
+    {
+        let mut query = self.db_client.prepare("
+            SELECT DISTINCT estimate, year
+                FROM acs_est_years
+                WHERE table_id = ?1
+                ORDER BY year
+        ")?;
+
+        let rows = query.query_map(&[&table_id], |row| {
+            (row.get(0), row.get(1))
+        })?;
+
+        let mut res = HashMap::new();
+        for row in rows {
+            let row = row?;
+            res.entry(row.0).or_insert(Vec::new()).push(row.1);
+        }
+        Ok(res)
+    }
+
+    /// Roll ups of the C table from the B table stored at refresh, for
+    /// each year and estimate.
+    pub fn query_rollups(
+        &mut self,
+        table_id: &str,
+        suffix: &Option<String>,
+        ) -> Result<HashMap<(Estimate, u32), Vec<StoredRollup>>>
+    {
+        let mut query = self.db_client.prepare("
+            SELECT estimate, year, c_column_id, b_column_ids, method
+                FROM acs_rollups
+                WHERE table_id = ?1 and suffix IS ?2
+                ORDER BY id
+        ").chain_err(|| "Error querying roll ups, try running refresh")?;
+
+        let rows = query.query_map(&[&table_id, suffix], |row| {
+            let b_column_ids: String = row.get(3);
+            let stored = StoredRollup {
+                c_column_id: row.get(2),
+                b_column_ids: b_column_ids.split(',')
+                    .filter(|id| !id.is_empty())
+                    .map(|id| id.to_owned())
+                    .collect(),
+                method: row.get(4),
+            };
+            let vintage: (Estimate, u32) = (row.get(0), row.get(1));
+            (vintage, stored)
+        })?;
+
+        let mut res = HashMap::new();
+        for row in rows {
+            let (vintage, stored) = row?;
+            res.entry(vintage).or_insert(Vec::new()).push(stored);
+        }
+        Ok(res)
+    }
+
+    /// Years and estimates for each B/C and suffix variant of a table id.
+    pub fn query_table_id_variant_est_years(
+        &mut self,
+        table_id: &str,
+        ) -> Result<BTreeMap<TableCode, HashMap<Estimate, Vec<u32>>>>
+    {
+        let mut query = self.db_client.prepare("
+            SELECT DISTINCT prefix, table_id, suffix, estimate, year
+                FROM acs_est_years
+                WHERE table_id = ?1
+                ORDER BY year
+        ")?;
+
+        let rows = query.query_map(&[&table_id], |row| {
+            let code = TableCode {
+                prefix: row.get(0),
+                table_id: row.get(1),
+                suffix: row.get(2),
+            };
+            (code, row.get(3), row.get(4))
+        })?;
+
+        let mut res = BTreeMap::new();
+        for row in rows {
+            let (code, estimate, year) = row?;
+            res.entry(code)
+                .or_insert_with(HashMap::new)
+                .entry(estimate)
+                .or_insert(Vec::new())
+                .push(year);
+        }
+        Ok(res)
+    }
+
+    /// Results are ranked by bm25, with hits on the table id weighted
+    /// more heavily than hits on the label. `limit` of `None` returns all
+    /// results.
     pub fn fulltext_search(
         &mut self,
         search: &str,
-        ) -> Result<Vec<TableRecord>>
+        limit: Option<u32>,
+        offset: u32,
+        ) -> Result<Vec<SearchRecord>>
     {
+        // bm25 weights are per column: prefix, table_id, suffix, label.
+        // snippet and highlight mark matched terms in the label and
+        // table_id, the marks are turned into ranges below.
         let sql_str = "
-            SELECT prefix, table_id, suffix, label
+            SELECT prefix, table_id, suffix, label,
+                highlight(acs_fts, 1, ?4, ?5),
+                snippet(acs_fts, 3, ?4, ?5, '...', 64)
                 FROM acs_fts
                 WHERE acs_fts MATCH ?1
-                ORDER BY table_id, prefix, suffix
+                ORDER BY bm25(acs_fts, 1.0, 10.0, 1.0, 1.0), table_id, prefix, suffix
+                LIMIT ?2 OFFSET ?3
         ";
+
+        let synonyms = self.synonyms()?;
+        let search = expand_synonyms(search, &synonyms);
+
+        // sqlite treats a negative limit as no limit
+        let limit = limit.map(|l| l as i64).unwrap_or(-1);
+        let offset = offset as i64;
+
         let mut query = self.db_client.prepare(&sql_str)?;
-        let records = query.query_map(&[&search], |row| {
-            TableRecord {
-                code: TableCode {
-                    prefix: row.get(0),
-                    table_id: row.get(1),
-                    suffix: row.get(2),
-                },
-                label: row.get(3),
+        let records = query.query_map(
+            &[&search, &limit, &offset, &MATCH_START, &MATCH_END],
+            |row| {
+                let table_id_marked: String = row.get(4);
+                let label_marked: String = row.get(5);
+                let (table_id, table_id_matches) = match_ranges(&table_id_marked);
+                let (label_snippet, label_matches) = match_ranges(&label_marked);
+
+                SearchRecord {
+                    table: TableRecord {
+                        code: TableCode {
+                            prefix: row.get(0),
+                            table_id: table_id,
+                            suffix: row.get(2),
+                        },
+                        label: row.get(3),
+                    },
+                    table_id_matches: table_id_matches,
+                    label_snippet: label_snippet,
+                    label_matches: label_matches,
+                }
             }
-        })?;
+        )?;
 
         let mut res = Vec::new();
         for record in records {
@@ -680,3 +1079,157 @@ impl Explorer {
         Ok(res)
     }
 }
+
+/// Removes the marks fts5 put around matched terms, returning the plain
+/// text and the (start, end) byte range of each match in it.
+fn match_ranges(marked: &str) -> (String, Vec<(usize, usize)>) {
+    let mut text = String::new();
+    let mut matches = Vec::new();
+    let mut start = 0;
+
+    for c in marked.chars() {
+        if MATCH_START.starts_with(c) {
+            start = text.len();
+        } else if MATCH_END.starts_with(c) {
+            matches.push((start, text.len()));
+        } else {
+            text.push(c);
+        }
+    }
+    (text, matches)
+}
+
+/// Reads table universes from a groups.json response into universe_map.
+/// First universe found for a table is kept.
+fn process_acs_groups_data(
+    groups_data: &str,
+    universe_map: &mut HashMap<TableCode, String>,
+    ) -> Result<()>
+{
+    let data = json::parse(groups_data)
+        .chain_err(|| "error parsing json response")?;
+
+    for group in data["groups"].members() {
+        let name = group["name"].to_string();
+
+        // api key has a trailing space in some years
+        let universe = if group["universe "].is_null() {
+            &group["universe"]
+        } else {
+            &group["universe "]
+        };
+        if universe.is_null() {
+            continue;
+        }
+
+        let code = match parse_table_code_only(name.as_bytes()).to_result() {
+            Ok(code) => code,
+            // not a table, e.g. a geography group
+            Err(_) => continue,
+        };
+
+        universe_map.entry(code).or_insert(universe.to_string().trim().to_owned());
+    }
+
+    Ok(())
+}
+
+// Census vocabulary that the porter stemmer won't connect on its own.
+// Synonyms are symmetric.
+const DEFAULT_SYNONYMS: &[(&str, &str)] = &[
+    ("kids", "children"),
+    ("kid", "child"),
+    ("hh", "household"),
+    ("hhs", "households"),
+    ("latino", "hispanic"),
+    ("black", "african american"),
+    ("job", "occupation"),
+    ("jobs", "employment"),
+    ("work", "employment"),
+    ("wage", "earnings"),
+    ("wages", "earnings"),
+    ("salary", "earnings"),
+    ("house", "housing"),
+    ("home", "housing"),
+    ("rent", "renter"),
+    ("elderly", "65 years and over"),
+    ("commute", "travel time to work"),
+    ("vet", "veteran"),
+];
+
+/// Expands each bare word in a fulltext search into an OR group of
+/// itself and its synonyms. Words containing fts5 syntax, and the
+/// operators AND, OR and NOT, are passed through as-is.
+fn expand_synonyms(search: &str, synonyms: &[(String, String)]) -> String {
+    search.split_whitespace().map(|word| {
+        let is_bare_word = word.chars().all(|c| c.is_alphanumeric());
+        let is_operator = ["AND", "OR", "NOT"].contains(&word);
+
+        if !is_bare_word || is_operator {
+            return word.to_owned();
+        }
+
+        let lower = word.to_lowercase();
+        let mut expansions: Vec<&str> = synonyms.iter()
+            .filter_map(|&(ref term, ref synonym)| {
+                if *term == lower {
+                    Some(synonym.as_str())
+                } else if *synonym == lower {
+                    Some(term.as_str())
+                } else {
+                    None
+                }
+            })
+            .collect();
+        expansions.sort();
+        expansions.dedup();
+
+        if expansions.is_empty() {
+            word.to_owned()
+        } else {
+            let mut group = vec![word.to_owned()];
+            // quoted as fts5 phrases, so multi-word synonyms stay together
+            group.extend(expansions.iter().map(|s| {
+                format!("\"{}\"", s.replace("\"", "\"\""))
+            }));
+            format!("({})", group.join(" OR "))
+        }
+    })
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_match_ranges() {
+        assert_eq!(
+            match_ranges("...Median \x02Income\x03 by \x02Race\x03"),
+            ("...Median Income by Race".to_owned(), vec![(10, 16), (20, 24)])
+        );
+        assert_eq!(match_ranges("25102"), ("25102".to_owned(), vec![]));
+    }
+
+    #[test]
+    fn test_expand_synonyms() {
+        let synonyms = vec![
+            ("kids".to_owned(), "children".to_owned()),
+            ("black".to_owned(), "african american".to_owned()),
+        ];
+
+        assert_eq!(
+            expand_synonyms("Kids income", &synonyms),
+            "(Kids OR \"children\") income"
+        );
+        assert_eq!(
+            expand_synonyms("children NOT black", &synonyms),
+            "(children OR \"kids\") NOT (black OR \"african american\")"
+        );
+        assert_eq!(
+            expand_synonyms("\"kids\" table_id:25102", &synonyms),
+            "\"kids\" table_id:25102"
+        );
+    }
+}
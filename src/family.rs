@@ -0,0 +1,156 @@
+// Race and ethnicity iterations of a table: the same table id with a
+// suffix, e.g. B19013A for White alone householders.
+//
+// Each iteration is compared against the first table of the family
+// (the base table, if there is one) to show whether it has the same
+// columns and years.
+
+use acs::{
+    Estimate,
+    TableColumn,
+    TableRecord,
+    format_available_years,
+};
+use diff::path_key;
+
+use std::collections::HashMap;
+
+/// Meaning of a table suffix. Puerto Rico tables have a "PR" suffix,
+/// after the iteration letter if there is one.
+pub fn suffix_meaning(suffix: &str) -> Option<String> {
+    let iteration = |letter: &str| {
+        let meaning = match letter {
+            "A" => "White alone",
+            "B" => "Black or African American alone",
+            "C" => "American Indian and Alaska Native alone",
+            "D" => "Asian alone",
+            "E" => "Native Hawaiian and Other Pacific Islander alone",
+            "F" => "Some other race alone",
+            "G" => "Two or more races",
+            "H" => "White alone, not Hispanic or Latino",
+            "I" => "Hispanic or Latino",
+            _ => return None,
+        };
+        Some(meaning.to_owned())
+    };
+
+    let suffix = suffix.to_uppercase();
+
+    if suffix == "PR" {
+        Some("Puerto Rico".to_owned())
+    } else if suffix.ends_with("PR") {
+        iteration(&suffix[..suffix.len() - 2])
+            .map(|meaning| format!("{}, Puerto Rico", meaning))
+    } else {
+        iteration(&suffix)
+    }
+}
+
+/// One table of a family
+pub struct FamilyMember {
+    pub table: TableRecord,
+    pub est_years: HashMap<Estimate, Vec<u32>>,
+    /// Estimate columns of the latest version of the table
+    pub columns: Vec<TableColumn>,
+}
+
+fn same_structure(a: &[TableColumn], b: &[TableColumn]) -> bool {
+    a.len() == b.len() &&
+    a.iter().zip(b.iter()).all(|(a, b)| {
+        a.code.column_id == b.code.column_id && path_key(a) == path_key(b)
+    })
+}
+
+/// Members should be sorted, so that the base table is first.
+pub fn format_table_family(members: &[FamilyMember]) -> String {
+    let base = match members.get(0) {
+        Some(base) => base,
+        None => return String::new(),
+    };
+    let base_years = format_available_years(&base.est_years);
+
+    let mut res = format!("\nTable {}{} Family:\n============================================\n\n",
+        base.table.code.prefix,
+        base.table.code.table_id,
+    );
+    res.push_str(&format!("{:10}| {:50}| {:8}| {:10}| {}\n",
+        "code", "suffix", "columns", "structure", "years"));
+    res.push_str(&format!("{}\n", "-".repeat(100)));
+
+    let mut different_structure = Vec::new();
+    let mut different_years = Vec::new();
+
+    for (i, member) in members.iter().enumerate() {
+        let code = &member.table.code;
+
+        let meaning = match code.suffix {
+            Some(ref suffix) => {
+                suffix_meaning(suffix).unwrap_or_else(|| "(unknown suffix)".to_owned())
+            },
+            None => "(base table)".to_owned(),
+        };
+
+        let years = format_available_years(&member.est_years);
+
+        let (structure, years) = if i == 0 {
+            ("-".to_owned(), years)
+        } else {
+            let structure = if same_structure(&base.columns, &member.columns) {
+                "same"
+            } else {
+                different_structure.push(code.to_string());
+                "differs"
+            };
+            let years = if years == base_years {
+                "same".to_owned()
+            } else {
+                different_years.push(code.to_string());
+                years
+            };
+            (structure.to_owned(), years)
+        };
+
+        res.push_str(&format!("{:10}| {:50}| {:8}| {:10}| {}\n",
+            code.to_string(),
+            meaning,
+            member.columns.len(),
+            structure,
+            years,
+        ));
+    }
+
+    res.push_str("\n");
+    if members.len() == 1 {
+        res.push_str("No iterations of this table\n");
+    } else if different_structure.is_empty() && different_years.is_empty() {
+        res.push_str(&format!("All iterations have the same columns and years as {}\n", base.table.code));
+    } else {
+        if !different_structure.is_empty() {
+            res.push_str(&format!("Columns differ from {}: {}\n",
+                base.table.code,
+                different_structure.join(", "),
+            ));
+        }
+        if !different_years.is_empty() {
+            res.push_str(&format!("Years differ from {}: {}\n",
+                base.table.code,
+                different_years.join(", "),
+            ));
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suffix_meaning() {
+        assert_eq!(suffix_meaning("A"), Some("White alone".to_owned()));
+        assert_eq!(suffix_meaning("i"), Some("Hispanic or Latino".to_owned()));
+        assert_eq!(suffix_meaning("PR"), Some("Puerto Rico".to_owned()));
+        assert_eq!(suffix_meaning("HPR"), Some("White alone, not Hispanic or Latino, Puerto Rico".to_owned()));
+        assert_eq!(suffix_meaning("Z"), None);
+    }
+}
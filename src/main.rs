@@ -28,31 +28,52 @@
 extern crate clap;
 #[macro_use]
 extern crate error_chain;
+extern crate handlebars;
+#[macro_use]
 extern crate json;
 #[macro_use]
 extern crate nom;
 extern crate reqwest;
 extern crate rusqlite;
+extern crate rustyline;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate serde_yaml;
+extern crate termion;
 extern crate time;
 
 mod acs;
 mod cli;
 mod census;
+mod crosswalk;
+mod describe;
+mod diff;
 mod error;
+mod etl;
 mod explorer;
-
-use cli::{cli_command, Command, ExplorerCommand};
+mod family;
+mod picker;
+mod rollup;
+mod shell;
+mod suggest;
+mod template;
+mod timeline;
+mod tree;
+
+use cli::{cli_command, Command, ExplorerCommand, SynonymsCommand};
+use describe::{describe_tables, Unavailable};
 use error::*;
 use explorer::Explorer;
 // TODO move formatting to another module.
 use acs::{
     Estimate,
-    format_table_name,
-    format_describe_table_raw,
-    format_describe_table_pretty,
-    format_est_years,
-    format_etl_config,
+    est_year_ranges,
     format_fulltext_search_results,
+    format_table_families,
+    format_variable_history,
+    group_table_families,
 };
 
 use std::env;
@@ -64,6 +85,14 @@ use std::process;
 // file name for sqlite db acs vars store
 const DB_FILE: &str = "vars.db";
 const ACS_DIR: &str = ".acs-explorer";
+// file name for shell history
+const HISTORY_FILE: &str = "shell_history";
+// exit code when tables to describe or variables aren't found, to tell
+// apart from other errors (exit code 1)
+const NOT_FOUND_EXIT_CODE: i32 = 2;
+// exit code when tables exist, but have nothing in the vintage or
+// columns asked for
+const UNAVAILABLE_EXIT_CODE: i32 = 3;
 
 fn main() {
     if let Err(ref err) = run() {
@@ -136,69 +165,115 @@ fn exec_command(explorer: &mut Explorer, explorer_command: ExplorerCommand) -> R
             println!("Overall refresh time: {}", end - start);
         },
 
-        FulltextSearch(search) => {
-            let records = explorer.fulltext_search(&search)?;
+        FulltextSearch { search, limit, offset, interactive, group } => {
+            if interactive && picker::is_tty() {
+                return picker::run(explorer, current_year as u32, &search);
+            }
+
+            if search.is_empty() {
+                return Err("No text entered".into());
+            }
+
+            // when grouping, limit and offset apply to groups, not
+            // individual tables
+            let records = if group {
+                explorer.fulltext_search(&search, None, 0)?
+            } else {
+                explorer.fulltext_search(&search, limit, offset)?
+            };
 
             if records.is_empty() {
                 println!("No results for search: {:?}", search);
                 process::exit(0);
             }
 
-            let out = format_fulltext_search_results(records);
+            let out = if group {
+                let mut families: Vec<_> = group_table_families(&records)
+                    .into_iter()
+                    .skip(offset as usize)
+                    .take(limit.map(|l| l as usize).unwrap_or(usize::max_value()))
+                    .collect();
+
+                for family in &mut families {
+                    let variant_est_years = explorer.query_table_id_variant_est_years(&family.table_id)?;
+                    family.variants = variant_est_years.keys().cloned().collect();
+
+                    let est_years = explorer.query_table_id_est_years(&family.table_id)?;
+                    family.years = est_year_ranges(&est_years);
+                }
+
+                format_table_families(&families)
+            } else {
+                format_fulltext_search_results(records, picker::is_tty())
+            };
             println!("{}", out);
         },
 
-        DescribeTable{ ref query, etl_config, etl_config_all, raw } => {
-            // prefix checked to be Some already, so can unwrap
-            let records = explorer.describe_table(
-                query.prefix.as_ref().unwrap(),
-                &query.table_id,
-                &query.suffix,
+        DescribeTable{ ref queries, ref options } => {
+            let (out, missing) = describe_tables(
+                explorer,
+                current_year as u32,
+                queries,
+                options,
             )?;
 
-            let suffix_display = match query.suffix {
-                Some(ref s) => s,
-                None => "",
-            };
+            if !out.is_empty() {
+                println!("{}", out);
+            }
 
-            if records.is_empty() {
-                println!("Table {}{}{} not found.",
-                    query.prefix.as_ref().unwrap(),
-                    query.table_id,
-                    suffix_display,
-                );
-                process::exit(0);
+            // errors go at the end, so they're not lost in batch output
+            for unavailable in &missing {
+                eprintln!("error: {}", unavailable);
+            }
+            if missing.iter().any(Unavailable::is_not_found) {
+                process::exit(NOT_FOUND_EXIT_CODE);
+            } else if !missing.is_empty() {
+                process::exit(UNAVAILABLE_EXIT_CODE);
             }
+        },
 
-            // from cli, etl_config and raw are guaranteed to not both be true at same time.
-            let mut out = if etl_config {
-                format_etl_config(current_year as u32, records, etl_config_all)
-            } else if raw {
-                format_describe_table_raw(current_year as u32, records)
-            } else {
-                format_describe_table_pretty(current_year as u32, records)
-            };
+        DescribeVariable(code) => {
+            let records = explorer.describe_table(
+                &code.table_code.prefix,
+                &code.table_code.table_id,
+                &code.table_code.suffix,
+            )?;
 
-            if !(raw || etl_config) {
-                out.push_str("Table Information:\n============================================\n\n");
-                let table_info = explorer.query_by_table_id(
-                    &query.prefix,
-                    &query.table_id,
-                    &query.suffix
-                )?;
-                if let Some(table_record) = table_info.get(0) {
-                    out.push_str(&format_table_name(&table_record));
-                    out.push_str("\n");
-                }
+            match format_variable_history(&code, &records) {
+                Some(out) => println!("{}", out),
+                None => {
+                    eprintln!("error: Variable {} not found.", code);
+                    process::exit(NOT_FOUND_EXIT_CODE);
+                },
+            }
+        },
 
-                let est_years = explorer.query_est_years(
-                    query.prefix.as_ref().unwrap(),
-                    &query.table_id,
-                    &query.suffix
-                )?;
-                out.push_str(&format_est_years(&est_years));
+        Shell => {
+            let mut history_path = PathBuf::from(ACS_DIR);
+            history_path.push(HISTORY_FILE);
+
+            shell::run(explorer, current_year as u32, &history_path)?;
+        },
+
+        Synonyms(synonyms_command) => {
+            match synonyms_command {
+                SynonymsCommand::List => {
+                    for (term, synonym) in explorer.synonyms()? {
+                        println!("{} | {}", term, synonym);
+                    }
+                },
+                SynonymsCommand::Add { term, synonym } => {
+                    explorer.add_synonym(&term, &synonym)?;
+                    println!("Added synonym {:?} for {:?}", synonym, term);
+                },
+                SynonymsCommand::Remove { term, synonym } => {
+                    if explorer.remove_synonym(&term, &synonym)? == 0 {
+                        println!("No synonym {:?} for {:?}", synonym, term);
+                    } else {
+                        println!("Removed synonym {:?} for {:?}", synonym, term);
+                    }
+                },
             }
-            println!("{}", out);
         },
     }
 
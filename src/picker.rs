@@ -0,0 +1,276 @@
+// Interactive picker: filter search results as you type, and select
+// a table to describe.
+//
+// Keys in list view:
+// - typing filters results (last word is matched as prefix)
+// - Up/Down select
+// - Enter describes selected table
+// - Esc or Ctrl-c quits
+//
+// Keys in describe view:
+// - Up/Down, PageUp/PageDown scroll
+// - Esc, q, Left or Backspace go back to list
+
+use acs::{SearchRecord, TableCode};
+use describe::describe_pretty;
+use error::*;
+use explorer::Explorer;
+
+use std::io::{self, Write};
+use termion;
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+use termion::screen::AlternateScreen;
+use termion::{clear, cursor, style};
+
+const PROMPT: &str = "search> ";
+
+pub fn is_tty() -> bool {
+    termion::is_tty(&io::stdout())
+}
+
+pub fn run(explorer: &mut Explorer, current_year: u32, initial_search: &str) -> Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout().into_raw_mode()?;
+    let mut screen = AlternateScreen::from(stdout);
+
+    let mut picker = Picker {
+        search: initial_search.to_owned(),
+        results: Vec::new(),
+        selected: 0,
+        view: View::List,
+    };
+    picker.update_results(explorer)?;
+    picker.draw(&mut screen)?;
+
+    for key in stdin.keys() {
+        let key = key?;
+
+        let quit = match picker.view {
+            View::List => picker.handle_list_key(explorer, current_year, key)?,
+            View::Describe { .. } => {
+                picker.handle_describe_key(key);
+                false
+            },
+        };
+
+        if quit {
+            break;
+        }
+
+        picker.draw(&mut screen)?;
+    }
+
+    write!(screen, "{}", cursor::Show)?;
+    screen.flush()?;
+
+    Ok(())
+}
+
+enum View {
+    List,
+    Describe {
+        lines: Vec<String>,
+        scroll: usize,
+    },
+}
+
+struct Picker {
+    search: String,
+    results: Vec<SearchRecord>,
+    selected: usize,
+    view: View,
+}
+
+impl Picker {
+    /// Returns true if picker should quit
+    fn handle_list_key(
+        &mut self,
+        explorer: &mut Explorer,
+        current_year: u32,
+        key: Key,
+        ) -> Result<bool>
+    {
+        match key {
+            Key::Esc | Key::Ctrl('c') => return Ok(true),
+            Key::Char('\n') => {
+                if let Some(record) = self.results.get(self.selected) {
+                    let code = &record.table.code;
+                    let out = describe_pretty(
+                        explorer,
+                        current_year,
+                        &code.prefix,
+                        &code.table_id,
+                        &code.suffix,
+                    )?
+                    .unwrap_or_else(|| {
+                        format!("Table {} not found.", format_code(code))
+                    });
+
+                    self.view = View::Describe {
+                        lines: out.lines().map(|line| line.to_owned()).collect(),
+                        scroll: 0,
+                    };
+                }
+            },
+            Key::Up => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+            },
+            Key::Down => {
+                if self.selected + 1 < self.results.len() {
+                    self.selected += 1;
+                }
+            },
+            Key::Backspace => {
+                self.search.pop();
+                self.update_results(explorer)?;
+            },
+            Key::Char(c) => {
+                self.search.push(c);
+                self.update_results(explorer)?;
+            },
+            _ => (),
+        }
+        Ok(false)
+    }
+
+    fn handle_describe_key(&mut self, key: Key) {
+        let page = terminal_height().saturating_sub(1);
+
+        let go_back = match self.view {
+            View::Describe { ref lines, ref mut scroll } => {
+                let max_scroll = lines.len().saturating_sub(page);
+                match key {
+                    Key::Esc | Key::Char('q') | Key::Left | Key::Backspace => true,
+                    Key::Up => {
+                        *scroll = scroll.saturating_sub(1);
+                        false
+                    },
+                    Key::Down => {
+                        *scroll = (*scroll + 1).min(max_scroll);
+                        false
+                    },
+                    Key::PageUp => {
+                        *scroll = scroll.saturating_sub(page);
+                        false
+                    },
+                    Key::PageDown | Key::Char(' ') => {
+                        *scroll = (*scroll + page).min(max_scroll);
+                        false
+                    },
+                    _ => false,
+                }
+            },
+            View::List => false,
+        };
+
+        if go_back {
+            self.view = View::List;
+        }
+    }
+
+    fn update_results(&mut self, explorer: &mut Explorer) -> Result<()> {
+        self.selected = 0;
+
+        let search = prefix_search(&self.search);
+        if search.is_empty() {
+            self.results.clear();
+            return Ok(());
+        }
+
+        let limit = terminal_height().saturating_sub(1) as u32;
+
+        // a half-typed search may not be valid fts5 syntax, so
+        // just show no results until it is.
+        self.results = explorer.fulltext_search(&search, Some(limit), 0)
+            .unwrap_or_else(|_| Vec::new());
+
+        Ok(())
+    }
+
+    fn draw<W: Write>(&self, out: &mut W) -> Result<()> {
+        let (width, height) = terminal_size();
+
+        write!(out, "{}{}{}", clear::All, cursor::Goto(1, 1), cursor::Hide)?;
+
+        match self.view {
+            View::List => {
+                let rows = self.results.iter()
+                    .take(height.saturating_sub(1))
+                    .enumerate();
+
+                for (i, record) in rows {
+                    let line = format!("{:9} | {}",
+                        format_code(&record.table.code),
+                        record.table.label,
+                    );
+                    let line = truncate(&line, width);
+
+                    if i == self.selected {
+                        write!(out, "{}{}{}\r\n", style::Invert, line, style::Reset)?;
+                    } else {
+                        write!(out, "{}\r\n", line)?;
+                    }
+                }
+
+                write!(out, "{}{}{}{}",
+                    cursor::Goto(1, height as u16),
+                    PROMPT,
+                    self.search,
+                    cursor::Show,
+                )?;
+            },
+            View::Describe { ref lines, scroll } => {
+                for line in lines.iter().skip(scroll).take(height.saturating_sub(1)) {
+                    write!(out, "{}\r\n", truncate(line, width))?;
+                }
+
+                write!(out, "{}{}(Up/Down/PageUp/PageDown to scroll, q to go back){}",
+                    cursor::Goto(1, height as u16),
+                    style::Invert,
+                    style::Reset,
+                )?;
+            },
+        }
+
+        out.flush()?;
+        Ok(())
+    }
+}
+
+/// Matches last word of search as a prefix, so that results
+/// can update while a word is being typed.
+fn prefix_search(search: &str) -> String {
+    let search = search.trim_left();
+
+    match search.chars().last() {
+        Some(c) if c.is_alphanumeric() => format!("{}*", search),
+        _ => search.trim_right().to_owned(),
+    }
+}
+
+fn format_code(code: &TableCode) -> String {
+    let mut res = code.prefix.to_string();
+    res.push_str(&code.table_id);
+    if let Some(ref suffix) = code.suffix {
+        res.push_str(suffix);
+    }
+    res
+}
+
+fn truncate(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+fn terminal_size() -> (usize, usize) {
+    termion::terminal_size()
+        .map(|(w, h)| (w as usize, h as usize))
+        .unwrap_or((80, 24))
+}
+
+fn terminal_height() -> usize {
+    terminal_size().1
+}
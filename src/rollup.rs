@@ -0,0 +1,610 @@
+// How the columns of a collapsed C table roll up from its B table.
+//
+// A C table has the same universe as the B table with the same id, but
+// fewer, broader categories. Each C column is the sum of one or more B
+// columns, inferred from the label hierarchy:
+// 1. same label path: the B column itself
+// 2. the only unmatched C column under a matched parent: all unmatched
+//    B columns under that parent
+// 3. otherwise, unmatched B columns under a matched parent go into the
+//    C column whose dollar or number range contains theirs, e.g.
+//    "$1,500 to $1,999" into "$1,500 or more"
+//
+// The roll up is inferred for each year and estimate at refresh and
+// stored by column id; describe reads it back with the columns.
+
+use acs::{
+    Estimate,
+    TableColumn,
+    VariableRecord,
+    VariableType,
+    est_years_json,
+    format_available_years,
+    grep_records,
+    table_columns,
+};
+use diff::path_key;
+
+use json::JsonValue;
+use rusqlite;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, ValueRef};
+use std::collections::HashMap;
+use std::f64;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RollupMethod {
+    Same,
+    Remainder,
+    Range,
+}
+
+impl fmt::Display for RollupMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RollupMethod::Same => write!(f, "same"),
+            RollupMethod::Remainder => write!(f, "remainder"),
+            RollupMethod::Range => write!(f, "range"),
+        }
+    }
+}
+
+impl ToSql for RollupMethod {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput> {
+        Ok(ToSqlOutput::from(self.to_string()))
+    }
+}
+
+impl FromSql for RollupMethod {
+    fn column_result(value: ValueRef) -> FromSqlResult<RollupMethod> {
+        value.as_str().and_then(|val| {
+            match val {
+                "same" => Ok(RollupMethod::Same),
+                "remainder" => Ok(RollupMethod::Remainder),
+                "range" => Ok(RollupMethod::Range),
+                _ => Err(FromSqlError::InvalidType),
+            }
+        })
+    }
+}
+
+/// B columns summed into one C column
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rollup {
+    pub c_column: TableColumn,
+    /// empty if no B columns could be inferred
+    pub b_columns: Vec<TableColumn>,
+    pub method: Option<RollupMethod>,
+}
+
+/// A roll up as stored in the db, by column id
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredRollup {
+    pub c_column_id: String,
+    pub b_column_ids: Vec<String>,
+    pub method: Option<RollupMethod>,
+}
+
+impl<'a> From<&'a Rollup> for StoredRollup {
+    fn from(rollup: &Rollup) -> Self {
+        StoredRollup {
+            c_column_id: rollup.c_column.code.column_id.clone(),
+            b_column_ids: rollup.b_columns.iter()
+                .map(|column| column.code.column_id.clone())
+                .collect(),
+            method: rollup.method.clone(),
+        }
+    }
+}
+
+/// Columns should be estimate columns of the same year and estimate.
+pub fn rollup(b: &[TableColumn], c: &[TableColumn]) -> Vec<Rollup> {
+    let b_keys: Vec<String> = b.iter().map(path_key).collect();
+    let mut b_used = vec![false; b.len()];
+    let mut mapped: Vec<(Vec<usize>, Option<RollupMethod>)> = vec![(Vec::new(), None); c.len()];
+
+    // 1. same label path
+    for (i, c_column) in c.iter().enumerate() {
+        let key = path_key(c_column);
+        if let Some(j) = b_keys.iter().position(|b_key| *b_key == key) {
+            mapped[i] = (vec![j], Some(RollupMethod::Same));
+            b_used[j] = true;
+        }
+    }
+
+    // 2 and 3. unmatched C columns, grouped by parent
+    let mut parents_done: Vec<String> = Vec::new();
+    for i in 0..c.len() {
+        if mapped[i].1.is_some() || c[i].path.len() < 2 {
+            continue;
+        }
+        let parent_path = &c[i].path[..c[i].path.len() - 1];
+        let parent_key = parent_path.iter()
+            .map(|level| level.to_lowercase())
+            .collect::<Vec<_>>()
+            .join("!!");
+
+        if parents_done.contains(&parent_key) {
+            continue;
+        }
+        parents_done.push(parent_key.clone());
+
+        // B parent must be matched to a C column by label
+        let b_parent = match b_keys.iter().position(|b_key| *b_key == parent_key) {
+            Some(b_parent) if b_used[b_parent] => b_parent,
+            _ => continue,
+        };
+
+        let siblings: Vec<usize> = (i..c.len())
+            .filter(|&k| {
+                mapped[k].1.is_none() &&
+                c[k].path.len() == c[i].path.len() &&
+                c[k].path.starts_with(parent_path)
+            })
+            .collect();
+
+        let candidates: Vec<usize> = (0..b.len())
+            .filter(|&j| {
+                !b_used[j] &&
+                b[j].path.len() == b[b_parent].path.len() + 1 &&
+                b[j].path.starts_with(&b[b_parent].path)
+            })
+            .collect();
+
+        if siblings.len() == 1 {
+            for &j in &candidates {
+                b_used[j] = true;
+            }
+            mapped[siblings[0]] = (candidates, Some(RollupMethod::Remainder));
+            continue;
+        }
+
+        for &j in &candidates {
+            let b_range = match b[j].path.last().and_then(|label| label_range(label)) {
+                Some(b_range) => b_range,
+                None => continue,
+            };
+
+            let found = siblings.iter().cloned().find(|&k| {
+                c[k].path.last()
+                    .and_then(|label| label_range(label))
+                    .map(|c_range| c_range.contains(&b_range))
+                    .unwrap_or(false)
+            });
+
+            if let Some(k) = found {
+                b_used[j] = true;
+                mapped[k].0.push(j);
+                mapped[k].1 = Some(RollupMethod::Range);
+            }
+        }
+    }
+
+    c.iter().zip(mapped.into_iter())
+        .map(|(c_column, (b_indices, method))| {
+            Rollup {
+                c_column: c_column.clone(),
+                b_columns: b_indices.into_iter().map(|j| b[j].clone()).collect(),
+                method: method,
+            }
+        })
+        .collect()
+}
+
+/// Estimate columns of a table in one year and estimate.
+pub fn vintage_columns(records: &[VariableRecord], estimate: &Estimate, year: u32) -> Vec<TableColumn> {
+    let mut records: Vec<VariableRecord> = records.iter()
+        .filter(|record| record.year == year && record.estimate == *estimate)
+        .cloned()
+        .collect();
+    records.sort();
+
+    table_columns(&records).into_iter()
+        .filter(|column| column.code.var_type == VariableType::Value)
+        .collect()
+}
+
+/// Years and estimates both B and C tables are in, five-year estimate
+/// first.
+pub fn shared_vintages(b_records: &[VariableRecord], c_records: &[VariableRecord]) -> Vec<(Estimate, u32)> {
+    let mut vintages: Vec<(Estimate, u32)> = c_records.iter()
+        .filter(|c_record| {
+            b_records.iter().any(|b_record| {
+                b_record.year == c_record.year && b_record.estimate == c_record.estimate
+            })
+        })
+        .map(|record| (record.estimate.clone(), record.year))
+        .collect();
+    vintages.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    vintages.dedup();
+    vintages
+}
+
+/// Stored roll ups with the columns they refer to. C columns not in
+/// `c` are left out, as are B column ids not in `b`.
+pub fn resolve_rollups(b: &[TableColumn], c: &[TableColumn], stored: &[StoredRollup]) -> Vec<Rollup> {
+    stored.iter()
+        .filter_map(|stored_rollup| {
+            let c_column = match c.iter().find(|column| column.code.column_id == stored_rollup.c_column_id) {
+                Some(c_column) => c_column,
+                None => return None,
+            };
+            let b_columns = stored_rollup.b_column_ids.iter()
+                .filter_map(|id| b.iter().find(|column| column.code.column_id == *id))
+                .cloned()
+                .collect();
+
+            Some(Rollup {
+                c_column: c_column.clone(),
+                b_columns: b_columns,
+                method: stored_rollup.method.clone(),
+            })
+        })
+        .collect()
+}
+
+/// B columns and stored roll ups for one year and estimate. With
+/// `grep`, only C columns matching the keyword are kept, each still
+/// with all the B columns summed into it.
+pub fn vintage_rollup(
+    b_records: &[VariableRecord],
+    c_records: &[VariableRecord],
+    stored: &[StoredRollup],
+    estimate: &Estimate,
+    year: u32,
+    grep: &Option<String>,
+    ) -> (Vec<TableColumn>, Vec<Rollup>)
+{
+    let b_columns = vintage_columns(b_records, estimate, year);
+    let c_columns = vintage_columns(c_records, estimate, year);
+    let mut rollups = resolve_rollups(&b_columns, &c_columns, stored);
+
+    match *grep {
+        Some(ref keyword) => {
+            let grep_columns = |records: &[VariableRecord]| {
+                let records: Vec<VariableRecord> = records.iter()
+                    .filter(|record| record.year == year && record.estimate == *estimate)
+                    .cloned()
+                    .collect();
+                vintage_columns(&grep_records(records, keyword), estimate, year)
+            };
+
+            let c_ids: Vec<String> = grep_columns(c_records).into_iter()
+                .map(|column| column.code.column_id)
+                .collect();
+            rollups.retain(|rollup| c_ids.contains(&rollup.c_column.code.column_id));
+
+            (grep_columns(b_records), rollups)
+        },
+        None => (b_columns, rollups),
+    }
+}
+
+/// B leaf columns not included in any C column, directly or through a
+/// parent.
+pub fn unmapped_b_columns(b: &[TableColumn], rollups: &[Rollup]) -> Vec<TableColumn> {
+    b.iter()
+        .filter(|b_column| b_column.is_leaf)
+        .filter(|b_column| {
+            !rollups.iter()
+                .flat_map(|rollup| rollup.b_columns.iter())
+                .any(|mapped| b_column.path.starts_with(&mapped.path))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Range of values a label covers, e.g. "$800 to $1,499".
+#[derive(Debug, Clone, PartialEq)]
+struct LabelRange {
+    low: f64,
+    high: f64,
+    // for "less than"
+    high_exclusive: bool,
+}
+
+impl LabelRange {
+    fn contains(&self, other: &LabelRange) -> bool {
+        self.low <= other.low && (
+            other.high < self.high ||
+            (other.high == self.high && (!self.high_exclusive || other.high_exclusive))
+        )
+    }
+}
+
+fn label_range(label: &str) -> Option<LabelRange> {
+    let label = label.to_lowercase();
+
+    let numbers: Vec<f64> = label
+        .split(|c: char| !(c.is_digit(10) || c == '.' || c == ','))
+        .map(|number| number.replace(",", ""))
+        .filter(|number| number.chars().any(|c| c.is_digit(10)))
+        .filter_map(|number| number.trim_right_matches('.').parse::<f64>().ok())
+        .collect();
+
+    let range = |low, high, high_exclusive| {
+        Some(LabelRange { low: low, high: high, high_exclusive: high_exclusive })
+    };
+
+    match numbers.len() {
+        1 if label.contains("less than") || label.starts_with("under") => {
+            range(f64::NEG_INFINITY, numbers[0], true)
+        },
+        1 if label.contains("or more") || label.contains("and over") || label.contains("or over") ||
+            label.contains("or older") => {
+            range(numbers[0], f64::INFINITY, false)
+        },
+        1 => range(numbers[0], numbers[0], false),
+        2 => range(numbers[0], numbers[1], false),
+        _ => None,
+    }
+}
+
+/// Roll up for a run of vintages where it's the same
+pub struct VintageRollup {
+    pub est_years: HashMap<Estimate, Vec<u32>>,
+    pub b_columns: Vec<TableColumn>,
+    pub rollups: Vec<Rollup>,
+}
+
+/// Stored roll up for every year and estimate both tables are in.
+/// Vintages with the same roll up (by column id) are grouped together.
+pub fn rollup_vintages(
+    b_records: &[VariableRecord],
+    c_records: &[VariableRecord],
+    stored: &HashMap<(Estimate, u32), Vec<StoredRollup>>,
+    grep: &Option<String>,
+    ) -> Vec<VintageRollup>
+{
+    let mut res: Vec<VintageRollup> = Vec::new();
+
+    for (estimate, year) in shared_vintages(b_records, c_records) {
+        let stored_rollups = match stored.get(&(estimate.clone(), year)) {
+            Some(stored_rollups) => stored_rollups,
+            None => continue,
+        };
+        let (b_columns, rollups) = vintage_rollup(
+            b_records,
+            c_records,
+            stored_rollups,
+            &estimate,
+            year,
+            grep,
+        );
+        if rollups.is_empty() {
+            continue;
+        }
+
+        let same = res.iter().position(|vintage_rollup| {
+            rollup_ids(&vintage_rollup.rollups) == rollup_ids(&rollups) &&
+            column_ids(&vintage_rollup.b_columns) == column_ids(&b_columns)
+        });
+
+        match same {
+            Some(i) => {
+                res[i].est_years.entry(estimate).or_insert(Vec::new()).push(year);
+            },
+            None => {
+                let mut est_years = HashMap::new();
+                est_years.insert(estimate, vec![year]);
+                res.push(VintageRollup {
+                    est_years: est_years,
+                    b_columns: b_columns,
+                    rollups: rollups,
+                });
+            },
+        }
+    }
+    res
+}
+
+fn column_ids(columns: &[TableColumn]) -> Vec<&str> {
+    columns.iter().map(|column| column.code.column_id.as_str()).collect()
+}
+
+fn rollup_ids(rollups: &[Rollup]) -> Vec<(&str, Vec<&str>, Option<RollupMethod>)> {
+    rollups.iter()
+        .map(|rollup| (
+            rollup.c_column.code.column_id.as_str(),
+            column_ids(&rollup.b_columns),
+            rollup.method.clone(),
+        ))
+        .collect()
+}
+
+pub fn format_rollup_vintages(vintage_rollups: &[VintageRollup]) -> String {
+    let outputs: Vec<String> = vintage_rollups.iter()
+        .map(|vintage_rollup| {
+            format_rollup(
+                &vintage_rollup.b_columns,
+                &vintage_rollup.rollups,
+                &format_available_years(&vintage_rollup.est_years),
+            )
+        })
+        .collect();
+    outputs.join("\n")
+}
+
+/// For deriving C values from B data: each C variable with the B
+/// variables summed into it, for each run of vintages.
+pub fn rollup_vintages_json(vintage_rollups: &[VintageRollup]) -> JsonValue {
+    let res: Vec<JsonValue> = vintage_rollups.iter()
+        .map(|vintage_rollup| {
+            let columns: Vec<JsonValue> = vintage_rollup.rollups.iter()
+                .map(|rollup| {
+                    let b_codes: Vec<String> = rollup.b_columns.iter()
+                        .map(|column| column.code.to_string())
+                        .collect();
+                    let method = match rollup.method {
+                        Some(ref method) => method.to_string().into(),
+                        None => JsonValue::Null,
+                    };
+                    object!{
+                        "code" => rollup.c_column.code.to_string(),
+                        "label" => rollup.c_column.path.join(" > "),
+                        "from" => b_codes,
+                        "method" => method
+                    }
+                })
+                .collect();
+
+            let unmapped: Vec<String> = unmapped_b_columns(&vintage_rollup.b_columns, &vintage_rollup.rollups)
+                .iter()
+                .map(|column| column.code.to_string())
+                .collect();
+
+            object!{
+                "estimates" => est_years_json(&vintage_rollup.est_years),
+                "columns" => columns,
+                "unmapped" => unmapped
+            }
+        })
+        .collect();
+
+    res.into()
+}
+
+/// Summary of which B columns each C column is summed from.
+/// `vintage` is the year and estimate the columns are from.
+pub fn format_rollup(b: &[TableColumn], rollups: &[Rollup], vintage: &str) -> String {
+    let (b_code, c_code) = match (b.get(0), rollups.get(0)) {
+        (Some(b_column), Some(rollup)) => {
+            (b_column.code.table_code.clone(), rollup.c_column.code.table_code.clone())
+        },
+        _ => return String::new(),
+    };
+
+    let mut res = format!("\n{} columns from {} ({}):\n============================================\n\n",
+        c_code,
+        b_code,
+        vintage,
+    );
+    res.push_str(&format!("{:6}| {:24}| {:10}| {}\n", "C col", "B columns", "method", "label"));
+    res.push_str("------+-------------------------+-----------+------------------------\n");
+
+    let mut unmapped_c = Vec::new();
+
+    for rollup in rollups {
+        let b_ids: Vec<&str> = rollup.b_columns.iter()
+            .map(|column| column.code.column_id.as_str())
+            .collect();
+        let method = match rollup.method {
+            Some(ref method) => method.to_string(),
+            None => {
+                unmapped_c.push(rollup.c_column.code.column_id.clone());
+                "-".to_owned()
+            },
+        };
+
+        res.push_str(&format!("{:6}| {:24}| {:10}| {}\n",
+            rollup.c_column.code.column_id,
+            if b_ids.is_empty() { "?".to_owned() } else { b_ids.join(" + ") },
+            method,
+            rollup.c_column.path.join(" > "),
+        ));
+    }
+
+    let unmapped_b: Vec<String> = unmapped_b_columns(b, rollups).into_iter()
+        .map(|column| column.code.column_id)
+        .collect();
+
+    if !unmapped_c.is_empty() || !unmapped_b.is_empty() {
+        res.push_str("\n");
+    }
+    if !unmapped_c.is_empty() {
+        res.push_str(&format!("C columns with no B columns found: {}\n", unmapped_c.join(", ")));
+    }
+    if !unmapped_b.is_empty() {
+        res.push_str(&format!("B columns not in any C column: {}\n", unmapped_b.join(", ")));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acs::fixtures::{column, record};
+
+    #[test]
+    fn test_rollup() {
+        let b = vec![
+            column("B25102_001E", &["Total"], false),
+            column("B25102_002E", &["Total", "With a mortgage"], false),
+            column("B25102_003E", &["Total", "With a mortgage", "Less than $800"], true),
+            column("B25102_004E", &["Total", "With a mortgage", "$800 to $1,499"], true),
+            column("B25102_005E", &["Total", "With a mortgage", "$1,500 to $1,999"], true),
+            column("B25102_006E", &["Total", "With a mortgage", "$2,000 or more"], true),
+            column("B25102_007E", &["Total", "Not mortgaged"], false),
+            column("B25102_008E", &["Total", "Not mortgaged", "Less than $800"], true),
+            column("B25102_009E", &["Total", "Not mortgaged", "$800 or more"], true),
+        ];
+        let c = vec![
+            column("C25102_001E", &["Total"], false),
+            column("C25102_002E", &["Total", "With a mortgage"], false),
+            column("C25102_003E", &["Total", "With a mortgage", "Less than $1,500"], true),
+            column("C25102_004E", &["Total", "With a mortgage", "$1,500 or more"], true),
+            column("C25102_005E", &["Total", "Not mortgaged"], true),
+        ];
+
+        let rollups = rollup(&b, &c);
+
+        let summary: Vec<(&str, Vec<&str>, Option<RollupMethod>)> = rollups.iter()
+            .map(|rollup| (
+                rollup.c_column.code.column_id.as_str(),
+                rollup.b_columns.iter().map(|column| column.code.column_id.as_str()).collect(),
+                rollup.method.clone(),
+            ))
+            .collect();
+
+        assert_eq!(summary, vec![
+            ("001", vec!["001"], Some(RollupMethod::Same)),
+            ("002", vec!["002"], Some(RollupMethod::Same)),
+            ("003", vec!["003", "004"], Some(RollupMethod::Range)),
+            ("004", vec!["005", "006"], Some(RollupMethod::Range)),
+            ("005", vec!["007"], Some(RollupMethod::Same)),
+        ]);
+
+        assert!(unmapped_b_columns(&b, &rollups).is_empty());
+    }
+
+    #[test]
+    fn test_rollup_vintages_stored() {
+        let b_records = vec![
+            record("B25102_001E", "Total:", 2015, Estimate::FiveYear),
+            record("B25102_002E", "Total:!!With a mortgage:", 2015, Estimate::FiveYear),
+            record("B25102_003E", "Total:!!With a mortgage:!!Less than $800", 2015, Estimate::FiveYear),
+            record("B25102_004E", "Total:!!With a mortgage:!!$800 or more", 2015, Estimate::FiveYear),
+            record("B25102_005E", "Total:!!Not mortgaged", 2015, Estimate::FiveYear),
+        ];
+        let c_records = vec![
+            record("C25102_001E", "Total:", 2015, Estimate::FiveYear),
+            record("C25102_002E", "Total:!!With a mortgage", 2015, Estimate::FiveYear),
+            record("C25102_003E", "Total:!!Not mortgaged", 2015, Estimate::FiveYear),
+        ];
+
+        let rollups = rollup(
+            &vintage_columns(&b_records, &Estimate::FiveYear, 2015),
+            &vintage_columns(&c_records, &Estimate::FiveYear, 2015),
+        );
+        let mut stored = HashMap::new();
+        stored.insert(
+            (Estimate::FiveYear, 2015),
+            rollups.iter().map(StoredRollup::from).collect::<Vec<_>>(),
+        );
+
+        let vintage_rollups = rollup_vintages(&b_records, &c_records, &stored, &None);
+        assert_eq!(vintage_rollups.len(), 1);
+        assert_eq!(vintage_rollups[0].rollups, rollups);
+
+        // grep keeps matching C columns and their parents, each with
+        // all its B columns
+        let grepped = rollup_vintages(&b_records, &c_records, &stored, &Some("not mortgaged".to_owned()));
+        assert_eq!(rollup_ids(&grepped[0].rollups), vec![
+            ("001", vec!["001"], Some(RollupMethod::Same)),
+            ("003", vec!["005"], Some(RollupMethod::Same)),
+        ]);
+
+        // nothing stored for the vintage, nothing shown
+        assert!(rollup_vintages(&b_records, &c_records, &HashMap::new(), &None).is_empty());
+    }
+}
@@ -0,0 +1,289 @@
+// Interactive shell, keeps one Explorer (and db connection) open
+// across many queries.
+//
+// Commands:
+// - search <text>
+// - describe <table id>
+// - vars <table id>
+// - years <table id>
+// - :format json|table
+// - help
+// - quit
+
+use acs::{
+    TableCode,
+    est_years_json,
+    format_describe_table_raw,
+    format_fulltext_search_results,
+    table_record_json,
+    variable_record_json,
+};
+use cli::{parse_table_query, DescribeFormat, DescribeOptions, TableIdQuery, Vintage};
+use describe::{describe_tables, table_information, table_not_found};
+use error::*;
+use explorer::Explorer;
+use picker;
+
+use json::JsonValue;
+use rustyline;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+use std::path::Path;
+
+const PROMPT: &str = "acs> ";
+const COMMANDS: &[&str] = &["search", "describe", "vars", "years", ":format", "help", "quit"];
+
+const HELP: &str = "\
+commands:
+    search <text>           fulltext search for an acs table
+    describe <table id>     get information about a table, both B and C without a prefix
+    vars <table id>         all variables of a table, for every year and estimate
+    years <table id>        years and estimates a table is available for
+    :format json|table      switch output format
+    help                    print this message
+    quit                    exit shell (also Ctrl-d)
+";
+
+#[derive(Debug, Clone, PartialEq)]
+enum OutputFormat {
+    Table,
+    Json,
+}
+
+pub fn run(explorer: &mut Explorer, current_year: u32, history_path: &Path) -> Result<()> {
+    let completer = TableCompleter {
+        table_codes: explorer.table_codes()?
+            .iter()
+            .map(|code| code.to_string())
+            .collect(),
+    };
+
+    let mut rl = Editor::<TableCompleter>::new();
+    rl.set_completer(Some(completer));
+
+    // no history yet on first run
+    let _ = rl.load_history(history_path);
+
+    let mut format = OutputFormat::Table;
+
+    println!("ACS Explorer shell. Type `help` for commands.");
+
+    loop {
+        let line = match rl.readline(PROMPT) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into()),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        rl.add_history_entry(line);
+
+        let (command, arg) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+
+        let res = match command {
+            "quit" | "exit" | ":q" => break,
+            "help" => {
+                print!("{}", HELP);
+                Ok(())
+            },
+            ":format" => {
+                match arg {
+                    "json" => format = OutputFormat::Json,
+                    "table" => format = OutputFormat::Table,
+                    _ => println!("format must be one of: json, table"),
+                }
+                Ok(())
+            },
+            "search" | "s" => search(explorer, &format, arg),
+            "describe" | "d" => describe(explorer, current_year, &format, arg),
+            "vars" => vars(explorer, current_year, &format, arg),
+            "years" => years(explorer, current_year, &format, arg),
+            _ => {
+                println!("Unknown command {:?}, type `help` for commands", command);
+                Ok(())
+            },
+        };
+
+        // errors in one command shouldn't end the session
+        if let Err(err) = res {
+            println!("error: {}", err);
+        }
+    }
+
+    rl.save_history(history_path)?;
+
+    Ok(())
+}
+
+fn search(explorer: &mut Explorer, format: &OutputFormat, search: &str) -> Result<()> {
+    if search.is_empty() {
+        return Err("No text entered".into());
+    }
+
+    let records = explorer.fulltext_search(search, None, 0)?;
+
+    match *format {
+        OutputFormat::Table => {
+            if records.is_empty() {
+                println!("No results for search: {:?}", search);
+            } else {
+                print!("{}", format_fulltext_search_results(records, picker::is_tty()));
+            }
+        },
+        OutputFormat::Json => {
+            let res: Vec<JsonValue> = records.iter()
+                .map(|record| table_record_json(&record.table))
+                .collect();
+            println!("{}", JsonValue::from(res).pretty(2));
+        },
+    }
+    Ok(())
+}
+
+fn describe(
+    explorer: &mut Explorer,
+    current_year: u32,
+    format: &OutputFormat,
+    table: &str,
+    ) -> Result<()>
+{
+    let query = parse_table_arg(table)?;
+
+    let options = DescribeOptions {
+        format: match *format {
+            OutputFormat::Table => DescribeFormat::Pretty,
+            OutputFormat::Json => DescribeFormat::Json,
+        },
+        etl_config_all: false,
+        vintage: Vintage::default(),
+        moe: false,
+        grep: None,
+        batch: false,
+    };
+
+    // same as the describe subcommand, so a missing prefix describes
+    // both B and C tables
+    let (out, missing) = describe_tables(explorer, current_year, &[query], &options)?;
+
+    if !out.is_empty() {
+        println!("{}", out);
+    }
+    for unavailable in &missing {
+        eprintln!("error: {}", unavailable);
+    }
+    Ok(())
+}
+
+fn vars(
+    explorer: &mut Explorer,
+    current_year: u32,
+    format: &OutputFormat,
+    table: &str,
+    ) -> Result<()>
+{
+    let query = parse_table_arg(table)?;
+    let code = query_code(&query)?;
+
+    let mut records = explorer.describe_table(&code.prefix, &code.table_id, &code.suffix)?;
+    if records.is_empty() {
+        eprintln!("error: {}", table_not_found(explorer, code)?);
+        return Ok(());
+    }
+
+    match *format {
+        OutputFormat::Table => {
+            print!("{}", format_describe_table_raw(current_year, records));
+        },
+        OutputFormat::Json => {
+            records.sort();
+            let res: Vec<JsonValue> = records.iter()
+                .map(variable_record_json)
+                .collect();
+            println!("{}", JsonValue::from(res).pretty(2));
+        },
+    }
+    Ok(())
+}
+
+fn years(explorer: &mut Explorer, current_year: u32, format: &OutputFormat, table: &str) -> Result<()> {
+    let query = parse_table_arg(table)?;
+    let code = query_code(&query)?;
+
+    match *format {
+        OutputFormat::Table => {
+            let records = explorer.describe_table(&code.prefix, &code.table_id, &code.suffix)?;
+            print!("{}", table_information(
+                explorer,
+                current_year,
+                &code.prefix,
+                &code.table_id,
+                &code.suffix,
+                &records,
+            )?);
+        },
+        OutputFormat::Json => {
+            let est_years = explorer.query_est_years(&code.prefix, &code.table_id, &code.suffix)?;
+            println!("{}", est_years_json(&est_years).pretty(2));
+        },
+    }
+    Ok(())
+}
+
+fn parse_table_arg(table: &str) -> Result<TableIdQuery> {
+    if table.is_empty() {
+        return Err("Table id required for query".into());
+    }
+
+    parse_table_query(table.as_bytes())
+        .to_result()
+        .map_err(|_| format!("{:?} is not a valid Table ID format", table).into())
+}
+
+fn query_code(query: &TableIdQuery) -> Result<TableCode> {
+    match query.prefix {
+        Some(ref prefix) => Ok(TableCode {
+            prefix: prefix.clone(),
+            table_id: query.table_id.clone(),
+            suffix: query.suffix.clone(),
+        }),
+        None => Err("Prefix required for table code".into()),
+    }
+}
+
+/// Completes commands at the start of the line, and table ids after.
+struct TableCompleter {
+    table_codes: Vec<String>,
+}
+
+impl Completer for TableCompleter {
+    fn complete(&self, line: &str, pos: usize) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let word = &line[start..pos];
+
+        let candidates = if start == 0 {
+            COMMANDS.iter()
+                .filter(|command| command.starts_with(word))
+                .map(|command| command.to_string())
+                .collect()
+        } else {
+            let word = word.to_uppercase();
+            self.table_codes.iter()
+                .filter(|code| code.starts_with(&word))
+                .cloned()
+                .collect()
+        };
+
+        Ok((start, candidates))
+    }
+}
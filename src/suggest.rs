@@ -0,0 +1,187 @@
+// "Did you mean" suggestions for table codes that aren't found:
+// the same table with the other prefix, other suffixes (e.g. only race
+// iterations exist), and codes within a small edit distance.
+
+use acs::{TableCode, TablePrefix};
+
+use std::fmt;
+
+// typos are usually one or two digits swapped or mistyped
+const MAX_DISTANCE: usize = 2;
+const MAX_SIMILAR: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Suggestions {
+    /// Same table id and suffix, other prefix
+    pub other_prefix: Vec<TableCode>,
+    /// Same prefix and table id, other suffix
+    pub other_suffix: Vec<TableCode>,
+    /// Nearest codes by edit distance
+    pub similar: Vec<TableCode>,
+}
+
+impl Suggestions {
+    pub fn is_empty(&self) -> bool {
+        self.other_prefix.is_empty() &&
+        self.other_suffix.is_empty() &&
+        self.similar.is_empty()
+    }
+}
+
+impl fmt::Display for Suggestions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return Ok(());
+        }
+
+        let join = |codes: &[TableCode]| {
+            codes.iter().map(|code| code.to_string()).collect::<Vec<_>>().join(", ")
+        };
+
+        write!(f, "Did you mean:")?;
+        if !self.other_prefix.is_empty() {
+            write!(f, "\n    {} (other prefix)", join(&self.other_prefix))?;
+        }
+        if !self.other_suffix.is_empty() {
+            write!(f, "\n    {} (other suffix)", join(&self.other_suffix))?;
+        }
+        if !self.similar.is_empty() {
+            write!(f, "\n    {} (similar)", join(&self.similar))?;
+        }
+        Ok(())
+    }
+}
+
+/// Suggestions from all table `codes`. Without a prefix, tables with
+/// either prefix are suggested.
+pub fn suggest(
+    prefix: &Option<TablePrefix>,
+    table_id: &str,
+    suffix: &Option<String>,
+    codes: &[TableCode],
+    ) -> Suggestions
+{
+    let prefix_matches = |code: &TableCode| {
+        prefix.as_ref().map(|prefix| code.prefix == *prefix).unwrap_or(true)
+    };
+
+    let other_prefix: Vec<TableCode> = match *prefix {
+        Some(ref prefix) => codes.iter()
+            .filter(|code| {
+                code.prefix != *prefix && code.table_id == table_id && code.suffix == *suffix
+            })
+            .cloned()
+            .collect(),
+        None => Vec::new(),
+    };
+
+    let other_suffix: Vec<TableCode> = codes.iter()
+        .filter(|code| {
+            prefix_matches(*code) && code.table_id == table_id && code.suffix != *suffix
+        })
+        .cloned()
+        .collect();
+
+    // prefix only counts towards distance if one was given
+    let key = |prefix: &Option<TablePrefix>, table_id: &str, suffix: &Option<String>| {
+        let mut key = prefix.as_ref().map(|prefix| prefix.to_string()).unwrap_or_default();
+        key.push_str(table_id);
+        if let Some(ref suffix) = *suffix {
+            key.push_str(suffix);
+        }
+        key
+    };
+    let target = key(prefix, table_id, suffix);
+
+    let mut similar: Vec<(usize, TableCode)> = codes.iter()
+        .filter(|code| !other_prefix.contains(code) && !other_suffix.contains(code))
+        .filter_map(|code| {
+            let code_prefix = if prefix.is_some() { Some(code.prefix.clone()) } else { None };
+            let distance = edit_distance(&target, &key(&code_prefix, &code.table_id, &code.suffix));
+
+            if distance <= MAX_DISTANCE {
+                Some((distance, code.clone()))
+            } else {
+                None
+            }
+        })
+        .collect();
+    similar.sort();
+
+    Suggestions {
+        other_prefix: other_prefix,
+        other_suffix: other_suffix,
+        similar: similar.into_iter()
+            .take(MAX_SIMILAR)
+            .map(|(_, code)| code)
+            .collect(),
+    }
+}
+
+/// Levenshtein distance, by chars
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+
+    // distances from the previous row of a to each prefix of b
+    let mut row: Vec<usize> = (0..b.len() + 1).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut prev_diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev_diagonal + if a_char == *b_char { 0 } else { 1 };
+            prev_diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code(prefix: TablePrefix, table_id: &str, suffix: Option<&str>) -> TableCode {
+        TableCode {
+            prefix: prefix,
+            table_id: table_id.to_owned(),
+            suffix: suffix.map(|s| s.to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("B25102", "B25102"), 0);
+        assert_eq!(edit_distance("B25102", "B25012"), 2);
+        assert_eq!(edit_distance("B25102", "B251023"), 1);
+        assert_eq!(edit_distance("", "B25"), 3);
+    }
+
+    #[test]
+    fn test_suggest() {
+        let codes = vec![
+            code(TablePrefix::B, "19013", Some("A")),
+            code(TablePrefix::B, "19013", Some("B")),
+            code(TablePrefix::B, "25102", None),
+            code(TablePrefix::C, "24010", None),
+            code(TablePrefix::B, "25012", None),
+        ];
+
+        let suggestions = suggest(&Some(TablePrefix::B), "24010", &None, &codes);
+        assert_eq!(suggestions.other_prefix, vec![code(TablePrefix::C, "24010", None)]);
+        assert!(suggestions.other_suffix.is_empty());
+
+        let suggestions = suggest(&Some(TablePrefix::B), "19013", &None, &codes);
+        assert_eq!(suggestions.other_suffix, vec![
+            code(TablePrefix::B, "19013", Some("A")),
+            code(TablePrefix::B, "19013", Some("B")),
+        ]);
+
+        let suggestions = suggest(&Some(TablePrefix::B), "25120", &None, &codes);
+        assert_eq!(suggestions.similar, vec![
+            code(TablePrefix::B, "25012", None),
+            code(TablePrefix::B, "25102", None),
+        ]);
+    }
+}
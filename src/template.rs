@@ -0,0 +1,85 @@
+// Table descriptions rendered through a user-supplied handlebars
+// template, for ETL systems that need a layout other than `--etl`.
+//
+// The template context is the `TableDescription` that `--format json`
+// serializes: table code and label, universe, availability, and each
+// version of the table with its columns. `columns` at the top level are
+// the columns of the latest version.
+
+use acs::TableDescription;
+use error::*;
+
+use handlebars::{self, Handlebars};
+
+const TEMPLATE_NAME: &str = "describe";
+
+/// A parsed template. Values aren't html-escaped, since output is
+/// usually yaml, sql or other text.
+pub struct Template {
+    handlebars: Handlebars,
+}
+
+impl Template {
+    pub fn new(source: &str) -> Result<Template> {
+        let mut handlebars = Handlebars::new();
+        handlebars.register_escape_fn(handlebars::no_escape);
+        handlebars.register_template_string(TEMPLATE_NAME, source)?;
+
+        Ok(Template {
+            handlebars: handlebars,
+        })
+    }
+
+    pub fn render(&self, context: &TableDescription) -> Result<String> {
+        Ok(self.handlebars.render(TEMPLATE_NAME, context)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acs::{Estimate, TableCode, TablePrefix, TableRecord, table_description};
+    use acs::fixtures::record;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_render_template() {
+        let table = TableRecord {
+            code: TableCode {
+                prefix: TablePrefix::B,
+                table_id: "25102".to_owned(),
+                suffix: None,
+            },
+            label: "Mortgage Status".to_owned(),
+        };
+        let records = vec![
+            record("B25102_001E", "Total:", 2015, Estimate::FiveYear),
+            record("B25102_002E", "Total:!!With a mortgage & loan", 2015, Estimate::FiveYear),
+        ];
+        let mut est_years = HashMap::new();
+        est_years.insert(Estimate::FiveYear, vec![2015]);
+        let context = table_description(2016, &table, None, &est_years, records);
+
+        let template = Template::new(concat!(
+            "{{code}}: {{label}}\n",
+            "{{#each availability}}{{@key}}:{{#each this}} {{this}}{{/each}}\n{{/each}}",
+            "{{#each versions}}{{min_year}}-{{max_year}}\n{{/each}}",
+            "{{#each columns}}",
+            "{{column_id}} {{#each label_path}}/{{this}}{{/each}}{{#if leaf}} (leaf){{/if}}\n",
+            "{{/each}}",
+        )).unwrap();
+
+        assert_eq!(template.render(&context).unwrap(), concat!(
+            "B25102: Mortgage Status\n",
+            "5yr: 2015\n",
+            "2015-2015\n",
+            "001 /Total\n",
+            "002 /Total/With a mortgage & loan (leaf)\n",
+        ));
+    }
+
+    #[test]
+    fn test_template_syntax_error() {
+        assert!(Template::new("{{#each columns}}").is_err());
+    }
+}
@@ -0,0 +1,173 @@
+// Timeline of the vintages each column of a table is in, for spotting
+// columns that drop out for a few years while the table itself is
+// still available.
+//
+// One row per column id, one cell per (estimate, year):
+// - "██" column is in that vintage, estimate and margin of error
+// - "E" only the estimate, its margin of error is missing
+// - "M" only the margin of error, its estimate is missing
+// - "·" table is in that vintage, but not the column
+// - blank: table isn't in that vintage
+
+use acs::{
+    Estimate,
+    VariableRecord,
+    VariableType,
+    label_path,
+};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const PRESENT: &str = "██ ";
+const ESTIMATE_ONLY: &str = "E  ";
+const MOE_ONLY: &str = "M  ";
+const MISSING: &str = "·  ";
+const NO_TABLE: &str = "   ";
+
+/// `est_years` are all the years of each estimate the table is in,
+/// since `records` may be filtered down to some columns.
+pub fn format_column_timeline(records: Vec<VariableRecord>, est_years: &HashMap<Estimate, Vec<u32>>) -> String {
+    let table_code = match records.get(0) {
+        Some(record) => record.code.table_code.clone(),
+        None => return String::new(),
+    };
+
+    let table_vintages: HashSet<(Estimate, u32)> = est_years.iter()
+        .flat_map(|(estimate, years)| {
+            years.iter().map(move |year| (estimate.clone(), *year))
+        })
+        .collect();
+
+    // vintages each column id and var type is in, and its label in the
+    // latest year
+    let mut column_vintages: HashSet<(String, VariableType, Estimate, u32)> = HashSet::new();
+    let mut column_labels: BTreeMap<String, (u32, String)> = BTreeMap::new();
+
+    for record in &records {
+        column_vintages.insert((
+            record.code.column_id.clone(),
+            record.code.var_type.clone(),
+            record.estimate.clone(),
+            record.year,
+        ));
+
+        if record.code.var_type != VariableType::Value {
+            continue;
+        }
+        let label = label_path(&record.label).pop().unwrap_or_default();
+        let latest = column_labels.entry(record.code.column_id.clone())
+            .or_insert((record.year, label.clone()));
+        if record.year > latest.0 {
+            *latest = (record.year, label);
+        }
+    }
+
+    // margin of error columns without an estimate still get a row
+    for &(ref column_id, _, _, _) in &column_vintages {
+        column_labels.entry(column_id.clone()).or_insert((0, String::new()));
+    }
+
+    // (estimate, all years from first to last), five-year first
+    let mut groups: Vec<(Estimate, Vec<u32>)> = Vec::new();
+    for estimate in &[Estimate::FiveYear, Estimate::OneYear] {
+        let years: Vec<u32> = table_vintages.iter()
+            .filter(|&&(ref e, _)| e == estimate)
+            .map(|&(_, year)| year)
+            .collect();
+
+        if let (Some(min), Some(max)) = (years.iter().min(), years.iter().max()) {
+            groups.push((estimate.clone(), (*min..*max + 1).collect()));
+        }
+    }
+
+    let mut res = format!("\nTable {} Column Timeline:\n============================================\n\n",
+        table_code,
+    );
+
+    res.push_str(&format!("{:5}", ""));
+    for &(ref estimate, ref years) in &groups {
+        let name = match *estimate {
+            Estimate::FiveYear => "5-year",
+            Estimate::OneYear => "1-year",
+        };
+        res.push_str(&format!("| {:width$}", name, width = years.len() * PRESENT.chars().count()));
+    }
+    res.push_str("|\n");
+
+    res.push_str(&format!("{:5}", "code"));
+    for &(_, ref years) in &groups {
+        res.push_str("| ");
+        for year in years {
+            res.push_str(&format!("{:02} ", year % 100));
+        }
+    }
+    res.push_str("| label\n");
+
+    res.push_str("-----");
+    for &(_, ref years) in &groups {
+        res.push_str(&format!("+{}", "-".repeat(years.len() * 3 + 1)));
+    }
+    res.push_str("+------------------------\n");
+
+    for (column_id, &(_, ref label)) in &column_labels {
+        res.push_str(&format!("{:5}", column_id));
+        for &(ref estimate, ref years) in &groups {
+            res.push_str("| ");
+            for year in years {
+                let has = |var_type: VariableType| {
+                    column_vintages.contains(&(column_id.clone(), var_type, estimate.clone(), *year))
+                };
+                let cell = match (has(VariableType::Value), has(VariableType::MarginOfError)) {
+                    (true, true) => PRESENT,
+                    (true, false) => ESTIMATE_ONLY,
+                    (false, true) => MOE_ONLY,
+                    (false, false) if table_vintages.contains(&(estimate.clone(), *year)) => MISSING,
+                    (false, false) => NO_TABLE,
+                };
+                res.push_str(cell);
+            }
+        }
+        res.push_str(&format!("| {}\n", label));
+    }
+
+    res.push_str("\n██ column available   E margin of error missing   M estimate missing\n");
+    res.push_str("· column missing   (blank) table not available\n");
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acs::fixtures::record;
+
+    #[test]
+    fn test_format_column_timeline() {
+        let records = vec![
+            record("B08301_001E", "Total:", 2009, Estimate::FiveYear),
+            record("B08301_001M", "Total:", 2009, Estimate::FiveYear),
+            record("B08301_002E", "Taxicab", 2009, Estimate::FiveYear),
+            record("B08301_002M", "Taxicab", 2009, Estimate::FiveYear),
+            record("B08301_001E", "Total:", 2010, Estimate::FiveYear),
+            record("B08301_001M", "Total:", 2010, Estimate::FiveYear),
+            // margin of error missing
+            record("B08301_001E", "Total:", 2012, Estimate::FiveYear),
+            record("B08301_002E", "Taxicab", 2012, Estimate::FiveYear),
+            record("B08301_002M", "Taxicab", 2012, Estimate::FiveYear),
+        ];
+
+        // 2011 has other columns, filtered out
+        let mut est_years = HashMap::new();
+        est_years.insert(Estimate::FiveYear, vec![2009, 2010, 2011, 2012]);
+
+        assert_eq!(format_column_timeline(records, &est_years), concat!(
+            "\nTable B08301 Column Timeline:\n============================================\n\n",
+            "     | 5-year      |\n",
+            "code | 09 10 11 12 | label\n",
+            "-----+-------------+------------------------\n",
+            "001  | ██ ██ ·  E  | Total\n",
+            "002  | ██ ·  ·  ██ | Taxicab\n",
+            "\n██ column available   E margin of error missing   M estimate missing\n",
+            "· column missing   (blank) table not available\n",
+        ));
+    }
+}
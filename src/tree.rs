@@ -0,0 +1,224 @@
+// Tree view of a table's label hierarchy, drawn with box-drawing
+// connectors.
+//
+// Each row is marked as a subtotal (has columns nested under it) or a
+// leaf. Rows deeper than a max depth can be collapsed into their
+// parent, and long labels wrap to the terminal width.
+
+use acs::{
+    TableColumn,
+    VariableRecord,
+    VariableType,
+    format_version_estimates,
+    get_table_versions,
+    table_columns,
+};
+
+use std::io;
+use termion;
+
+const SUBTOTAL: &str = "●";
+const LEAF: &str = "○";
+const COLLAPSED: &str = "▸";
+
+// labels are never wrapped narrower than this, even when deeply nested
+const MIN_LABEL_WIDTH: usize = 20;
+
+/// Width of stdout, or None if it's not a terminal.
+pub fn terminal_width() -> Option<usize> {
+    if termion::is_tty(&io::stdout()) {
+        termion::terminal_size().ok().map(|(width, _)| width as usize)
+    } else {
+        None
+    }
+}
+
+pub fn format_describe_table_tree(
+    current_year: u32,
+    records: Vec<VariableRecord>,
+    max_depth: Option<usize>,
+    width: Option<usize>,
+    ) -> String
+{
+    let versions = get_table_versions(current_year, records);
+
+    let mut res = "\nTable Columns:\n============================================\n\n".to_owned();
+    res.push_str(&format!("{} subtotal   {} leaf   {} collapsed\n\n", SUBTOTAL, LEAF, COLLAPSED));
+
+    for table_version in versions {
+        res.push_str(&format!("\
+            code | label      ({})\n\
+            -----+------------------------------------\n\
+        ", format_version_estimates(&table_version)));
+
+        let columns: Vec<TableColumn> = table_columns(&table_version.records).into_iter()
+            .filter(|column| column.code.var_type == VariableType::Value)
+            .collect();
+
+        res.push_str(&format_tree(&columns, max_depth, width));
+        res.push_str("------------------------------------------\n\n");
+    }
+    res
+}
+
+/// One row per column, hiding columns deeper than `max_depth`. Labels
+/// are wrapped to fit `width`, if given.
+pub fn format_tree(columns: &[TableColumn], max_depth: Option<usize>, width: Option<usize>) -> String {
+    let mut res = String::new();
+
+    for (i, column) in columns.iter().enumerate() {
+        if max_depth.map(|max| column.depth > max).unwrap_or(false) {
+            continue;
+        }
+
+        let hidden = if max_depth == Some(column.depth) {
+            columns[i + 1..].iter()
+                .filter(|other| is_descendant(column, other))
+                .count()
+        } else {
+            0
+        };
+
+        let marker = if column.is_leaf {
+            LEAF
+        } else if hidden > 0 {
+            COLLAPSED
+        } else {
+            SUBTOTAL
+        };
+
+        // connectors for each ancestor level, continuing down to
+        // later siblings of that ancestor
+        let mut stem = String::new();
+        for level in 1..column.depth {
+            if has_later_sibling(columns, i, level) {
+                stem.push_str("│   ");
+            } else {
+                stem.push_str("    ");
+            }
+        }
+
+        // the root has no connector
+        let (branch, continuation) = if column.depth == 0 {
+            ("", "")
+        } else if has_later_sibling(columns, i, column.depth) {
+            ("├── ", "│   ")
+        } else {
+            ("└── ", "    ")
+        };
+
+        let mut label = column.path.last().cloned().unwrap_or_default();
+        if hidden > 0 {
+            label.push_str(&format!(" (+{})", hidden));
+        }
+
+        // wrapped lines are indented under the label, with a connector
+        // down to children if there are any shown
+        let children = if !column.is_leaf && hidden == 0 { "│ " } else { "  " };
+
+        let lines = match width {
+            Some(width) => {
+                // "001  | ● " before stem
+                let used = 9 + stem.chars().count() + branch.chars().count() + children.chars().count();
+                wrap(&label, width.saturating_sub(used).max(MIN_LABEL_WIDTH))
+            },
+            None => vec![label],
+        };
+
+        for (j, line) in lines.iter().enumerate() {
+            if j == 0 {
+                res.push_str(&format!("{:5}| {} {}{}{}\n",
+                    column.code.column_id,
+                    marker,
+                    stem,
+                    branch,
+                    line,
+                ));
+            } else {
+                res.push_str(&format!("{:5}|   {}{}{}{}\n",
+                    "",
+                    stem,
+                    continuation,
+                    children,
+                    line,
+                ));
+            }
+        }
+    }
+    res
+}
+
+fn is_descendant(ancestor: &TableColumn, column: &TableColumn) -> bool {
+    column.path.len() > ancestor.path.len() && column.path.starts_with(&ancestor.path)
+}
+
+/// Whether the ancestor of column `i` at depth `level` (or the column
+/// itself) has a sibling after it.
+fn has_later_sibling(columns: &[TableColumn], i: usize, level: usize) -> bool {
+    let parent = &columns[i].path[..level];
+
+    columns[i + 1..].iter().any(|other| {
+        other.depth == level && &other.path[..level] == parent
+    })
+}
+
+/// Greedy word wrap. Words longer than width are left on their own line.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split_whitespace() {
+        let len = line.chars().count();
+        if len > 0 && len + 1 + word.chars().count() > width {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use acs::fixtures::column;
+
+    #[test]
+    fn test_format_tree() {
+        let columns = vec![
+            column("B25102_001E", &["Total"], false),
+            column("B25102_002E", &["Total", "With a mortgage"], false),
+            column("B25102_003E", &["Total", "With a mortgage", "Less than $800"], true),
+            column("B25102_004E", &["Total", "With a mortgage", "$800 or more"], true),
+            column("B25102_005E", &["Total", "Not mortgaged"], true),
+        ];
+
+        assert_eq!(format_tree(&columns, None, None), "\
+001  | ● Total
+002  | ● ├── With a mortgage
+003  | ○ │   ├── Less than $800
+004  | ○ │   └── $800 or more
+005  | ○ └── Not mortgaged
+");
+
+        assert_eq!(format_tree(&columns, Some(1), None), "\
+001  | ● Total
+002  | ▸ ├── With a mortgage (+2)
+005  | ○ └── Not mortgaged
+");
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("Worked in county of residence", 12), vec![
+            "Worked in",
+            "county of",
+            "residence",
+        ]);
+    }
+}